@@ -0,0 +1,129 @@
+//! # ESP32シミュレータ
+//!
+//! バックエンドのプロトコル処理（[`backend::firmware::Firmware`]）をホストで動かし、擬似端末（pty）で公開します。
+//! 表示されたパスをGUIの `ESP32_SIMULATOR_PORT` に渡すと、実機なしでGUIからの通信を試せます。
+//!
+//! ```bash
+//! cargo run -p backend --bin simulator             # 暗号化モード（ハンドシェイクに応答）
+//! cargo run -p backend --bin simulator -- --plain  # 平文モード（ハンドシェイクを拒否）
+//! cargo run -p backend --bin simulator -- --require-encryption  # セッション鍵以外のコマンドを拒否
+//! ```
+
+#[cfg(all(unix, not(target_os = "espidf")))]
+fn main() {
+    simulator::run();
+}
+
+#[cfg(not(all(unix, not(target_os = "espidf"))))]
+fn main() {
+    eprintln!("simulator requires a Unix host with pseudo-terminal support");
+}
+
+#[cfg(all(unix, not(target_os = "espidf")))]
+mod simulator {
+    use std::fs::File;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use backend::firmware::{Clock, Firmware, Poll};
+    use backend::system::HostSystem;
+    use esp32_tauri_crypto::{
+        CryptoSystem, KDF_PARAMS_ENV, PASSPHRASE_ENV, ResetReason, Role, create_default_crypto, create_provisioned_crypto,
+    };
+    use nix::pty::openpty;
+    use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+    use nix::unistd::ttyname;
+
+    /// スレッドをスリープさせて待機する時計（時刻はシミュレータの起動から）
+    struct SleepClock(Instant);
+
+    impl Clock for SleepClock {
+        fn delay_ms(&mut self, ms: u32) {
+            thread::sleep(Duration::from_millis(ms.into()));
+        }
+
+        fn now(&self) -> Duration {
+            self.0.elapsed()
+        }
+    }
+
+    /// ファームウェアのログを標準エラー出力に表示するロガー
+    ///
+    /// 標準出力はptyのパスなどの案内に使います。
+    struct StderrLogger;
+
+    impl log::Log for StderrLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Info
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                eprintln!("[{}] {}", record.level(), record.args());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: StderrLogger = StderrLogger;
+
+    /// GUIと同じ環境変数でプロビジョニングした事前共有鍵（未設定ならデモ用の固定鍵）
+    fn load_psk() -> CryptoSystem {
+        match (std::env::var(PASSPHRASE_ENV), std::env::var(KDF_PARAMS_ENV)) {
+            (Ok(passphrase), Ok(params)) => create_provisioned_crypto(&passphrase, &params, Role::Device).unwrap_or_else(|e| {
+                eprintln!("{} is not valid KdfParams JSON: {}", KDF_PARAMS_ENV, e);
+                std::process::exit(2);
+            }),
+            _ => create_default_crypto(Role::Device),
+        }
+    }
+
+    /// ptyを開いてファームウェアのプロトコル処理を動かし続ける
+    pub fn run() {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let plain = args.iter().any(|arg| arg == "--plain");
+        let require_encryption = args.iter().any(|arg| arg == "--require-encryption");
+        if plain && require_encryption {
+            eprintln!("--plain and --require-encryption cannot be combined");
+            std::process::exit(2);
+        }
+
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Info);
+        }
+
+        let psk = load_psk();
+        let pty = openpty(None, None).expect("Failed to open pseudo-terminal");
+        // エコーや改行の変換でフレームが壊れないよう、raw モードにする
+        let mut termios = tcgetattr(&pty.slave).expect("Failed to read terminal attributes");
+        cfmakeraw(&mut termios);
+        tcsetattr(&pty.slave, SetArg::TCSANOW, &termios).expect("Failed to set raw mode");
+        let path = ttyname(&pty.slave).expect("Failed to resolve pseudo-terminal path");
+
+        println!("🖥️ ESP32 simulator ({}) listening on {}", if plain { "plain" } else { "encrypted" }, path.display());
+        println!("   Connect the GUI with: ESP32_SIMULATOR_PORT={} npm run tauri dev", path.display());
+
+        // GUIが切断してもマスター側の読み取りがエラーにならないよう、スレーブ側は開いたままにする
+        let _slave = pty.slave;
+        let port = File::from(pty.master);
+
+        let mut reset_reason = ResetReason::PowerOn;
+        loop {
+            let transport = port.try_clone().expect("Failed to clone pseudo-terminal handle");
+            let mut firmware = Firmware::new(transport, SleepClock(Instant::now()), psk.clone())
+                .with_system_info(HostSystem::new().with_reset_reason(reset_reason))
+                .with_require_encryption(require_encryption);
+            if plain {
+                firmware = firmware.with_cipher_suites(&[]);
+            }
+
+            firmware.announce();
+            while firmware.poll() == Poll::Continue {}
+
+            // 実機の再起動と同様に、セッションを破棄して起動通知からやり直す
+            println!("🔄 Simulated restart");
+            reset_reason = ResetReason::Software;
+        }
+    }
+}
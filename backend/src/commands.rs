@@ -0,0 +1,460 @@
+//! # コマンドハンドラ
+//!
+//! コマンドは [`CommandHandler`] を実装して [`CommandRegistry`] に登録します。
+//! 起動時に登録したハンドラの説明（[`CommandInfo`]）は機能情報としてGUIに報告されるため、
+//! 製品バリエーションごとに登録するハンドラを変えるだけで、GUIに表示されるコマンドも変わります。
+//!
+//! ```rust
+//! use backend::commands::{CommandError, CommandHandler, CommandRegistry, Context};
+//! use esp32_tauri_crypto::{Command, CommandInfo, Privilege, Response};
+//!
+//! struct Unlock;
+//!
+//! impl CommandHandler for Unlock {
+//!     fn info(&self) -> CommandInfo {
+//!         CommandInfo::new("unlock", "Unlock the door").with_privilege(Privilege::Session)
+//!     }
+//!
+//!     fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+//!         Ok(Response::ok("🔓 Unlocked"))
+//!     }
+//! }
+//!
+//! let registry = CommandRegistry::builtin().with_handler(Unlock);
+//! assert!(registry.commands().iter().any(|command| command.name == "unlock"));
+//! ```
+
+use std::time::Duration;
+
+use esp32_tauri_crypto::{CipherSuite, Command, CommandInfo, DeviceInfo, DeviceStatus, ErrorCode, Privilege, Request, Response};
+use serde_json::json;
+
+use crate::firmware::Clock;
+use crate::system::SystemInfo;
+
+/// ハンドラが返すエラー（エラーレスポンスとして送信される）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    /// エラーコード
+    pub code: ErrorCode,
+    /// メッセージ
+    pub message: String,
+}
+
+impl CommandError {
+    /// 新しいエラーを作成
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// パラメータの不足・型の不一致
+    pub fn invalid_parameters() -> Self {
+        Self::new(ErrorCode::InvalidParameters, "Invalid parameters")
+    }
+}
+
+impl From<CommandError> for Response {
+    fn from(error: CommandError) -> Self {
+        Response::error(error.code, error.message)
+    }
+}
+
+/// ハンドラに渡す実行時の情報
+pub struct Context<'a> {
+    info: &'a DeviceInfo,
+    system: &'a dyn SystemInfo,
+    clock: &'a dyn Clock,
+    command_info: Option<&'a CommandInfo>,
+    cipher_suite: Option<CipherSuite>,
+    encrypted: bool,
+    restart_after_ms: Option<u32>,
+}
+
+impl<'a> Context<'a> {
+    /// 新しいコンテキストを作成
+    ///
+    /// # 引数
+    /// * `info` - ファームウェアの機能情報
+    /// * `system` - デバイスの稼働状態
+    /// * `clock` - ファームウェアの時計（[`Context::now`] の時刻）
+    /// * `command_info` - 実行するコマンドの説明（未登録のアクションは `None`）
+    /// * `cipher_suite` - 確立したセッション鍵の暗号スイート（ハンドシェイク前は `None`）
+    /// * `encrypted` - コマンドが暗号化されていたか
+    pub fn new(
+        info: &'a DeviceInfo,
+        system: &'a dyn SystemInfo,
+        clock: &'a dyn Clock,
+        command_info: Option<&'a CommandInfo>,
+        cipher_suite: Option<CipherSuite>,
+        encrypted: bool,
+    ) -> Self {
+        Self { info, system, clock, command_info, cipher_suite, encrypted, restart_after_ms: None }
+    }
+
+    /// ファームウェアの機能情報
+    pub fn device_info(&self) -> &DeviceInfo {
+        self.info
+    }
+
+    /// デバイスの稼働状態
+    pub fn system(&self) -> &dyn SystemInfo {
+        self.system
+    }
+
+    /// ファームウェアの時計による現在時刻（起動からの経過時間）
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// 実行するコマンドの説明
+    pub fn command_info(&self) -> Option<&CommandInfo> {
+        self.command_info
+    }
+
+    /// 確立したセッション鍵の暗号スイート
+    pub fn cipher_suite(&self) -> Option<CipherSuite> {
+        self.cipher_suite
+    }
+
+    /// セッション鍵が確立しているか
+    pub fn is_secure(&self) -> bool {
+        self.cipher_suite.is_some()
+    }
+
+    /// コマンドが暗号化されていたか（応答も暗号化される）
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// コマンドの説明（`CommandInfo::privilege`）に従って実行を許可するか検査
+    ///
+    /// # エラー
+    /// 平文のコマンドや、セッション確立前のコマンドは `ErrorCode::PermissionDenied`
+    pub fn authorize(&self) -> Result<(), CommandError> {
+        match self.command_info {
+            Some(info) if !info.privilege.allows(self.encrypted, self.is_secure()) => {
+                log::warn!("🔒 Command '{}' denied: requires {} privilege", info.name, info.privilege);
+                Err(CommandError::new(
+                    ErrorCode::PermissionDenied,
+                    format!("Command '{}' requires {} privilege", info.name, info.privilege),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 応答を送信した後、指定ミリ秒待ってから再起動する
+    pub fn request_restart(&mut self, delay_ms: u32) {
+        self.restart_after_ms = Some(delay_ms);
+    }
+
+    /// 要求された再起動までの待機時間
+    pub fn restart_after_ms(&self) -> Option<u32> {
+        self.restart_after_ms
+    }
+}
+
+/// コマンドの処理
+pub trait CommandHandler {
+    /// コマンドの説明（`name` がアクション名になる）
+    fn info(&self) -> CommandInfo;
+
+    /// コマンドを処理して応答を返す
+    ///
+    /// 応答のアクション名とリクエストIDは呼び出し側で付けます。
+    ///
+    /// # エラー
+    /// エラーはエラーレスポンスとして送信されます。
+    fn handle(&mut self, command: &Command, context: &mut Context) -> Result<Response, CommandError>;
+}
+
+/// 登録済みのハンドラ
+struct Entry {
+    info: CommandInfo,
+    handler: Box<dyn CommandHandler>,
+}
+
+/// アクション名からハンドラを引くレジストリ
+#[derive(Default)]
+pub struct CommandRegistry {
+    entries: Vec<Entry>,
+}
+
+impl CommandRegistry {
+    /// 空のレジストリを作成
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 共通ライブラリの [`Request`] に定義されたすべてのコマンドを登録したレジストリ
+    pub fn builtin() -> Self {
+        Self::new()
+            .with_handler(HelloHandler)
+            .with_handler(PingHandler)
+            .with_handler(StatusHandler)
+            .with_handler(InfoHandler)
+            .with_handler(EchoHandler)
+            .with_handler(RebootHandler)
+    }
+
+    /// ハンドラを登録（同じ名前のハンドラは置き換える）
+    pub fn register(&mut self, handler: impl CommandHandler + 'static) {
+        let info = handler.info();
+        let entry = Entry { info, handler: Box::new(handler) };
+        match self.entries.iter_mut().find(|existing| existing.info.name == entry.info.name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// ハンドラを登録したレジストリを返す
+    pub fn with_handler(mut self, handler: impl CommandHandler + 'static) -> Self {
+        self.register(handler);
+        self
+    }
+
+    /// ハンドラの登録を解除
+    ///
+    /// # 戻り値
+    /// 登録されていた場合は `true`
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.info.name != name);
+        self.entries.len() != len
+    }
+
+    /// 登録されたコマンドの説明（登録順）
+    pub fn commands(&self) -> Vec<CommandInfo> {
+        self.entries.iter().map(|entry| entry.info.clone()).collect()
+    }
+
+    /// アクション名に対応するハンドラと説明
+    pub fn get_mut(&mut self, name: &str) -> Option<(&CommandInfo, &mut (dyn CommandHandler + 'static))> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.info.name == name)
+            .map(|entry| (&entry.info, entry.handler.as_mut()))
+    }
+}
+
+/// コマンドを型付きのリクエストに変換
+fn parse(command: &Command) -> Result<Request, CommandError> {
+    Request::try_from(command).map_err(|_| CommandError::invalid_parameters())
+}
+
+/// `hello`: 疎通確認のメッセージを返す
+pub struct HelloHandler;
+
+impl CommandHandler for HelloHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("hello", "Reply with a greeting")
+    }
+
+    fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+        log::info!("👋 Processing hello command");
+        Ok(Response::ok("🎉 Hello from ESP32! Bidirectional crypto communication works!"))
+    }
+}
+
+/// `ping`: pongを返す
+pub struct PingHandler;
+
+impl CommandHandler for PingHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("ping", "Reply with pong")
+    }
+
+    fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+        log::info!("🏓 Processing ping command");
+        Ok(Response::ok("🏓 Pong from ESP32!"))
+    }
+}
+
+/// `status`: セッションの状態とデバイスの稼働状態（[`DeviceStatus`]）を返す
+pub struct StatusHandler;
+
+impl CommandHandler for StatusHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("status", "Report session, heap, uptime, reset reason and chip details")
+    }
+
+    fn handle(&mut self, _command: &Command, context: &mut Context) -> Result<Response, CommandError> {
+        log::info!("📊 Processing status command");
+        let system = context.system();
+        let status = DeviceStatus {
+            secure_session: context.is_secure(),
+            cipher_suite: context.cipher_suite(),
+            firmware_version: context.device_info().firmware_version.clone(),
+            idf_version: system.idf_version(),
+            chip: system.chip(),
+            uptime_ms: system.uptime_ms(),
+            reset_reason: system.reset_reason(),
+            heap: system.heap(),
+            tasks: system.task_stacks(),
+        };
+
+        let message = format!(
+            "✅ ESP32 is running normally ({}), up {} s, free heap {} bytes, last reset: {}",
+            if status.secure_session { "secure session established" } else { "no secure session" },
+            status.uptime_ms / 1000,
+            status.heap.free_bytes,
+            status.reset_reason
+        );
+        Ok(Response::ok(message).with_payload(json!(status)))
+    }
+}
+
+/// `info`: ファームウェアの機能情報を返す
+pub struct InfoHandler;
+
+impl CommandHandler for InfoHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("info", "Report firmware version and capabilities")
+    }
+
+    fn handle(&mut self, _command: &Command, context: &mut Context) -> Result<Response, CommandError> {
+        log::info!("ℹ️ Processing info command");
+        let info = context.device_info();
+        let message = format!("ℹ️ Firmware v{} ({})", info.firmware_version, info.build_hash);
+        Ok(Response::ok(message).with_payload(json!(info)))
+    }
+}
+
+/// `echo`: 受け取った文字列をそのまま返す
+pub struct EchoHandler;
+
+impl CommandHandler for EchoHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("echo", "Reply with the given text").with_params(json!({
+            "type": "object",
+            "properties": { "text": { "type": "string" } },
+            "required": ["text"],
+        }))
+    }
+
+    fn handle(&mut self, command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+        let Request::Echo { text } = parse(command)? else {
+            return Err(CommandError::invalid_parameters());
+        };
+        log::info!("🔁 Processing echo command");
+        Ok(Response::ok(text.as_str()).with_payload(json!({ "text": text })))
+    }
+}
+
+/// `reboot`: 応答後、指定ミリ秒待ってから再起動する
+///
+/// 再送されたコマンドで再起動を繰り返されないよう、セッション鍵で暗号化されたコマンドのみ受け付けます。
+pub struct RebootHandler;
+
+impl CommandHandler for RebootHandler {
+    fn info(&self) -> CommandInfo {
+        CommandInfo::new("reboot", "Restart the device after a delay")
+            .with_params(json!({
+                "type": "object",
+                "properties": { "delay_ms": { "type": "integer", "minimum": 0 } },
+                "required": ["delay_ms"],
+            }))
+            .with_privilege(Privilege::Session)
+    }
+
+    fn handle(&mut self, command: &Command, context: &mut Context) -> Result<Response, CommandError> {
+        let Request::Reboot { delay_ms } = parse(command)? else {
+            return Err(CommandError::invalid_parameters());
+        };
+        log::warn!("🔄 Rebooting in {} ms", delay_ms);
+        context.request_restart(delay_ms);
+        Ok(Response::ok(format!("🔄 Rebooting in {} ms", delay_ms)).with_payload(json!({ "delay_ms": delay_ms })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use esp32_tauri_crypto::{ChipInfo, HeapStats, ResetReason, TaskStack};
+
+    /// 時刻が進まない時計
+    struct FixedClock;
+
+    impl Clock for FixedClock {
+        fn delay_ms(&mut self, _ms: u32) {}
+
+        fn now(&self) -> Duration {
+            Duration::from_secs(65)
+        }
+    }
+
+    /// 固定の値を報告する稼働状態
+    struct FixedSystem;
+
+    impl SystemInfo for FixedSystem {
+        fn heap(&self) -> HeapStats {
+            HeapStats { free_bytes: 180_000, min_free_bytes: 120_000 }
+        }
+
+        fn uptime_ms(&self) -> u64 {
+            65_000
+        }
+
+        fn reset_reason(&self) -> ResetReason {
+            ResetReason::TaskWatchdog
+        }
+
+        fn chip(&self) -> ChipInfo {
+            ChipInfo { model: "ESP32-S3".to_string(), revision: 2, cores: 2 }
+        }
+
+        fn idf_version(&self) -> String {
+            "v5.1.2".to_string()
+        }
+
+        fn task_stacks(&self) -> Vec<TaskStack> {
+            vec![TaskStack { name: "main".to_string(), stack_high_water_mark: 1024 }]
+        }
+    }
+
+    #[test]
+    fn test_builtin_handles_every_request() {
+        let registry = CommandRegistry::builtin();
+        let names: Vec<String> = registry.commands().into_iter().map(|command| command.name).collect();
+        assert_eq!(names, Request::ACTIONS);
+    }
+
+    #[test]
+    fn test_register_replaces_and_unregister_removes() {
+        struct QuietPing;
+
+        impl CommandHandler for QuietPing {
+            fn info(&self) -> CommandInfo {
+                CommandInfo::new("ping", "Reply without a message")
+            }
+
+            fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+                Ok(Response::ok(""))
+            }
+        }
+
+        let mut registry = CommandRegistry::builtin().with_handler(QuietPing);
+        assert_eq!(registry.commands().len(), Request::ACTIONS.len());
+        assert_eq!(registry.get_mut("ping").unwrap().0.description, "Reply without a message");
+
+        assert!(registry.unregister("reboot"));
+        assert!(!registry.unregister("reboot"));
+        assert!(registry.get_mut("reboot").is_none());
+    }
+
+    #[test]
+    fn test_status_reports_system_details() {
+        let info = DeviceInfo::new("1.2.3", "abc1234", &[]);
+        let mut context = Context::new(&info, &FixedSystem, &FixedClock, None, Some(CipherSuite::Aes256Gcm), true);
+        let response = StatusHandler.handle(&Command::new("status"), &mut context).unwrap();
+
+        let status: DeviceStatus = serde_json::from_value(response.payload.unwrap()).unwrap();
+        assert!(status.secure_session);
+        assert_eq!(status.cipher_suite, Some(CipherSuite::Aes256Gcm));
+        assert_eq!(status.firmware_version, "1.2.3");
+        assert_eq!(status.reset_reason, ResetReason::TaskWatchdog);
+        assert_eq!(status.heap.min_free_bytes, 120_000);
+        assert_eq!(status.tasks[0].stack_high_water_mark, 1024);
+        assert!(response.message.contains("up 65 s"));
+    }
+}
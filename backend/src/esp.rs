@@ -0,0 +1,205 @@
+//! # esp-idf向けのアダプタ
+//!
+//! [`Firmware`] にコンソール（UART）とFreeRTOSのディレイ、ESP-IDFから取得する稼働状態を渡し、再起動を実行します。
+
+use std::ffi::CStr;
+use std::time::Duration;
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::hal::reset;
+use esp_idf_svc::sys;
+use esp32_tauri_crypto::{ChipInfo, HeapStats, Keyring, ResetReason, Role, TaskStack, create_default_crypto, create_provisioned_crypto};
+use esp32_tauri_crypto::transport::ConsoleTransport;
+
+use crate::commands::CommandRegistry;
+use crate::firmware::{Clock, Firmware, Poll};
+use crate::middleware::MiddlewareStack;
+use crate::system::SystemInfo;
+
+/// FreeRTOSのディレイで待機する時計
+pub struct FreeRtosClock;
+
+impl Clock for FreeRtosClock {
+    fn delay_ms(&mut self, ms: u32) {
+        FreeRtos::delay_ms(ms);
+    }
+
+    fn now(&self) -> Duration {
+        // SAFETY: esp_timerは起動時に初期化済み
+        let micros = unsafe { sys::esp_timer_get_time() };
+        Duration::from_micros(micros.max(0) as u64)
+    }
+}
+
+/// ESP-IDFのAPIで取得する稼働状態
+///
+/// スタックの余裕は、`status` を処理しているタスク（通信ループ）と名前で指定したタスクについて報告します。
+pub struct EspSystem {
+    tasks: Vec<&'static CStr>,
+}
+
+impl EspSystem {
+    /// 通信ループと `main` タスクを監視する
+    pub fn new() -> Self {
+        Self { tasks: vec![c"main"] }
+    }
+
+    /// スタックの余裕を報告するタスクを名前で追加
+    pub fn with_task(mut self, name: &'static CStr) -> Self {
+        self.tasks.push(name);
+        self
+    }
+}
+
+impl Default for EspSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemInfo for EspSystem {
+    fn heap(&self) -> HeapStats {
+        // SAFETY: 引数を取らず、どのタスクからでも呼べる
+        unsafe {
+            HeapStats {
+                free_bytes: sys::esp_get_free_heap_size(),
+                min_free_bytes: sys::esp_get_minimum_free_heap_size(),
+            }
+        }
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        // SAFETY: esp_timerは起動時に初期化済み
+        let micros = unsafe { sys::esp_timer_get_time() };
+        micros.max(0) as u64 / 1000
+    }
+
+    fn reset_reason(&self) -> ResetReason {
+        // SAFETY: 引数を取らず、どのタスクからでも呼べる
+        match unsafe { sys::esp_reset_reason() } {
+            sys::esp_reset_reason_t_ESP_RST_POWERON => ResetReason::PowerOn,
+            sys::esp_reset_reason_t_ESP_RST_EXT => ResetReason::External,
+            sys::esp_reset_reason_t_ESP_RST_SW => ResetReason::Software,
+            sys::esp_reset_reason_t_ESP_RST_PANIC => ResetReason::Panic,
+            sys::esp_reset_reason_t_ESP_RST_INT_WDT => ResetReason::InterruptWatchdog,
+            sys::esp_reset_reason_t_ESP_RST_TASK_WDT => ResetReason::TaskWatchdog,
+            sys::esp_reset_reason_t_ESP_RST_WDT => ResetReason::Watchdog,
+            sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => ResetReason::DeepSleep,
+            sys::esp_reset_reason_t_ESP_RST_BROWNOUT => ResetReason::Brownout,
+            sys::esp_reset_reason_t_ESP_RST_SDIO => ResetReason::Sdio,
+            sys::esp_reset_reason_t_ESP_RST_USB => ResetReason::Usb,
+            sys::esp_reset_reason_t_ESP_RST_JTAG => ResetReason::Jtag,
+            sys::esp_reset_reason_t_ESP_RST_EFUSE => ResetReason::Efuse,
+            sys::esp_reset_reason_t_ESP_RST_PWR_GLITCH => ResetReason::PowerGlitch,
+            sys::esp_reset_reason_t_ESP_RST_CPU_LOCKUP => ResetReason::CpuLockup,
+            _ => ResetReason::Unknown,
+        }
+    }
+
+    fn chip(&self) -> ChipInfo {
+        let mut info = sys::esp_chip_info_t::default();
+        // SAFETY: 有効な構造体へのポインタを渡す
+        unsafe { sys::esp_chip_info(&mut info) };
+
+        let model = match info.model {
+            sys::esp_chip_model_t_CHIP_ESP32 => "ESP32".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32S2 => "ESP32-S2".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32S3 => "ESP32-S3".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32C3 => "ESP32-C3".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32C2 => "ESP32-C2".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32C6 => "ESP32-C6".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32H2 => "ESP32-H2".to_string(),
+            sys::esp_chip_model_t_CHIP_ESP32P4 => "ESP32-P4".to_string(),
+            sys::esp_chip_model_t_CHIP_POSIX_LINUX => "Linux".to_string(),
+            // ESP_IDF_VERSION より新しいESP-IDFで追加されたチップ
+            other => format!("unknown ({})", other),
+        };
+        ChipInfo { model, revision: info.revision, cores: info.cores }
+    }
+
+    fn idf_version(&self) -> String {
+        // SAFETY: ESP-IDFが持つ静的なNUL終端文字列を返す
+        unsafe { CStr::from_ptr(sys::esp_get_idf_version()) }.to_string_lossy().into_owned()
+    }
+
+    fn task_stacks(&self) -> Vec<TaskStack> {
+        // SAFETY: NULLは呼び出したタスク自身を表す
+        let current = unsafe {
+            TaskStack {
+                name: CStr::from_ptr(sys::pcTaskGetName(std::ptr::null_mut())).to_string_lossy().into_owned(),
+                stack_high_water_mark: sys::uxTaskGetStackHighWaterMark(std::ptr::null_mut()),
+            }
+        };
+
+        let named = self.tasks.iter().filter_map(|name| {
+            // SAFETY: NUL終端の名前を渡す。見つからないタスク（終了したものなど）はNULLが返る
+            let handle = unsafe { sys::xTaskGetHandle(name.as_ptr()) };
+            (!handle.is_null()).then(|| TaskStack {
+                name: name.to_string_lossy().into_owned(),
+                // SAFETY: 上で取得した有効なハンドル
+                stack_high_water_mark: unsafe { sys::uxTaskGetStackHighWaterMark(handle) },
+            })
+        });
+        std::iter::once(current).chain(named).collect()
+    }
+}
+
+/// ESP32でのシンプルなUART通信ループ
+/// 
+/// 標準入力からフレーム化されたパケットを受信し、標準出力にフレームで応答を送信します。
+/// 暗号化されたコマンドには暗号化して応答し、`require-encryption` フィーチャーではセッション鍵以外のコマンドを拒否します。
+/// フレーム以外のデータ（手入力やノイズ）は破棄します。
+pub fn run_plain_uart_loop() -> ! {
+    run_uart_loop(provisioned_keyring(), CommandRegistry::builtin(), MiddlewareStack::standard())
+}
+
+/// ビルド時にプロビジョニングした事前共有鍵
+///
+/// `ESP32_TAURI_PASSPHRASE` と `ESP32_TAURI_KDF_PARAMS`（[`KdfParams`](esp32_tauri_crypto::KdfParams) のJSON）を
+/// 設定してビルドすると、その鍵を使います。未設定ならデモ用の固定鍵で、警告を出力します。
+///
+/// # パニック
+/// `ESP32_TAURI_KDF_PARAMS` が不正な場合（誤った鍵で起動しないため）
+pub fn provisioned_keyring() -> Keyring {
+    match (option_env!("ESP32_TAURI_PASSPHRASE"), option_env!("ESP32_TAURI_KDF_PARAMS")) {
+        (Some(passphrase), Some(params)) => {
+            let psk = create_provisioned_crypto(passphrase, params, Role::Device)
+                .expect("ESP32_TAURI_KDF_PARAMS must be valid KdfParams JSON");
+            Keyring::new(psk)
+        }
+        _ => {
+            log::warn!("⚠️ No provisioned key, using the demo key (set ESP32_TAURI_PASSPHRASE and ESP32_TAURI_KDF_PARAMS at build time)");
+            Keyring::new(create_default_crypto(Role::Device))
+        }
+    }
+}
+
+/// 登録したハンドラとミドルウェアでUART通信ループを実行
+///
+/// 事前共有鍵は `psks` で渡します（鍵のローテーション中は複数の鍵を登録します）。
+/// 製品バリエーションごとのコマンドは、起動時に `registry` に登録して渡します。
+/// `status` コマンドは [`EspSystem`] で取得した稼働状態を報告します。
+/// ログ・レート制限・権限チェックなどの共通処理は `middleware` で構成します。
+pub fn run_uart_loop(psks: Keyring, registry: CommandRegistry, middleware: MiddlewareStack) -> ! {
+    let mut firmware = Firmware::new(ConsoleTransport, FreeRtosClock, psks.active().clone())
+        .with_keyring(psks)
+        .with_registry(registry)
+        .with_middleware(middleware)
+        .with_system_info(EspSystem::new())
+        .with_require_encryption(cfg!(feature = "require-encryption"));
+    firmware.announce();
+    
+    loop {
+        if firmware.poll() == Poll::Restart {
+            reset::restart();
+        }
+        
+        // 短い遅延でWDTを避ける
+        FreeRtos::delay_ms(2);
+    }
+}
+
+/// 後方互換性のための関数（従来のインターフェース）
+pub fn run_communication_loop(_interval_ms: u32) {
+    run_plain_uart_loop();
+}
@@ -0,0 +1,690 @@
+//! # ハードウェアに依存しないプロトコル処理
+//!
+//! コマンドの解釈・応答・ハンドシェイクを、入出力（[`Read`] + [`Write`]）と
+//! 待機（[`Clock`]）を差し替えられる形で実装します。
+//! ESP32ではコンソールとFreeRTOSのディレイを渡し、テストではメモリ上のバッファを渡します。
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use esp32_tauri_crypto::{CipherSuite, Command, CryptoSystem, DeviceInfo, ErrorCode, Keyring, NonceStrategy, Response, WireCodec};
+use esp32_tauri_crypto::channel::{ChannelError, Packet, SecureChannel};
+use esp32_tauri_crypto::handshake::{Handshake, HandshakeMessage};
+use serde_json::json;
+
+use crate::commands::{CommandRegistry, Context};
+use crate::middleware::MiddlewareStack;
+use crate::system::{HostSystem, SystemInfo};
+
+/// ハンドシェイクで受け入れる暗号スイート（優先順）
+///
+/// AESはソフトウェア実装のため、ESP32ではChaCha20-Poly1305を優先します。
+pub const SUPPORTED_CIPHER_SUITES: &[CipherSuite] = &[
+    CipherSuite::ChaCha20Poly1305,
+    CipherSuite::XChaCha20Poly1305,
+    CipherSuite::Aes256Gcm,
+];
+
+/// ホストに報告するファームウェアの機能情報（コマンドは `Request` に定義されたもの）
+pub fn device_info() -> DeviceInfo {
+    DeviceInfo::new(env!("CARGO_PKG_VERSION"), env!("BUILD_HASH"), SUPPORTED_CIPHER_SUITES)
+}
+
+/// 待機と時刻の取得の手段
+///
+/// ESP32ではFreeRTOSのディレイとESPタイマー、テストでは待機時間の記録と仮想の時刻を使います。
+/// ミドルウェアは [`Context::now`] でこの時刻を参照します。
+pub trait Clock {
+    /// 指定したミリ秒だけ待機
+    fn delay_ms(&mut self, ms: u32);
+
+    /// 起動からの経過時間（単調増加）
+    fn now(&self) -> Duration;
+}
+
+/// 1回の受信処理の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    /// 受信を続ける
+    Continue,
+    /// 再起動が要求された（応答と待機は済んでいる）
+    Restart,
+}
+
+/// 通信セッションの状態
+struct Session {
+    /// 事前共有鍵（ハンドシェイクの認証用、Helloの鍵IDで選択）
+    psks: Keyring,
+    /// ハンドシェイクで確立したセッション鍵
+    crypto: Option<CryptoSystem>,
+}
+
+/// ファームウェアのプロトコル処理
+///
+/// 再起動など、チップに依存する操作は [`Poll`] で呼び出し側に任せます。
+pub struct Firmware<T, C> {
+    channel: SecureChannel<T>,
+    session: Session,
+    clock: C,
+    info: DeviceInfo,
+    registry: CommandRegistry,
+    middleware: MiddlewareStack,
+    system: Box<dyn SystemInfo>,
+    /// 平文のコマンドを拒否するか
+    require_encryption: bool,
+}
+
+impl<T: Read + Write, C: Clock> Firmware<T, C> {
+    /// 新しいプロトコル処理を作成
+    ///
+    /// # 引数
+    /// * `transport` - ホストとの入出力
+    /// * `clock` - 待機の手段
+    /// * `psk` - 事前共有鍵（ハンドシェイク前の通信とハンドシェイクの認証に使う）
+    ///
+    /// コマンドは [`MiddlewareStack::standard`] を通して [`CommandRegistry::builtin`] のハンドラで処理します。
+    /// 稼働状態は [`HostSystem`] で報告するため、実機では [`Firmware::with_system_info`] で置き換えてください。
+    pub fn new(transport: T, clock: C, psk: CryptoSystem) -> Self {
+        let registry = CommandRegistry::builtin();
+        Self {
+            channel: SecureChannel::new(transport, psk.clone()),
+            session: Session { psks: Keyring::new(psk), crypto: None },
+            clock,
+            info: device_info().with_commands(registry.commands()),
+            registry,
+            middleware: MiddlewareStack::standard(),
+            system: Box::new(HostSystem::new()),
+            require_encryption: false,
+        }
+    }
+
+    /// ハンドシェイクで受け付ける事前共有鍵を変更
+    ///
+    /// PSKのローテーション中は新旧の鍵を登録します。ハンドシェイク前の通信には現用の鍵を使います。
+    pub fn with_keyring(mut self, psks: Keyring) -> Self {
+        self.channel.set_crypto(psks.active().clone());
+        self.session = Session { psks, crypto: None };
+        self
+    }
+
+    /// コマンドを処理するハンドラを変更
+    ///
+    /// 起動通知と `info` の応答で報告する機能情報にも反映されます。
+    pub fn with_registry(mut self, registry: CommandRegistry) -> Self {
+        self.info = self.info.with_commands(registry.commands());
+        self.registry = registry;
+        self
+    }
+
+    /// ハンドシェイクで受け入れる暗号スイートを変更（デフォルトは [`SUPPORTED_CIPHER_SUITES`]）
+    ///
+    /// 空にするとハンドシェイクを拒否し、暗号化に対応していないファームウェアとして振る舞います。
+    /// 起動通知と `info` の応答で報告する機能情報にも反映されます。
+    pub fn with_cipher_suites(mut self, suites: &[CipherSuite]) -> Self {
+        self.info.cipher_suites = suites.to_vec();
+        self
+    }
+
+    /// コマンドの前後に実行するミドルウェアを変更
+    ///
+    /// 権限チェックも [`crate::middleware::Authorization`] で行うため、必要な場合はスタックに含めてください。
+    pub fn with_middleware(mut self, middleware: MiddlewareStack) -> Self {
+        self.middleware = middleware;
+        self
+    }
+
+    /// `status` コマンドで報告する稼働状態の取得手段を変更
+    pub fn with_system_info(mut self, system: impl SystemInfo + 'static) -> Self {
+        self.system = Box::new(system);
+        self
+    }
+
+    /// ハンドシェイクで確立したセッション鍵で暗号化されたコマンドのみを受け付ける
+    ///
+    /// 平文のコマンドと、PSKで暗号化したコマンドを拒否します。リプレイウィンドウはRAMにしかなく
+    /// 再起動で消えるため、PSKで暗号化したコマンドは再起動後に再送できてしまうからです。
+    /// ハンドシェイクはMACで認証されるため、このモードでも平文で受け付けます。
+    pub fn with_require_encryption(mut self, required: bool) -> Self {
+        self.require_encryption = required;
+        self
+    }
+
+    /// 入出力への参照
+    pub fn transport(&self) -> &T {
+        self.channel.get_ref()
+    }
+
+    /// 入出力への可変参照
+    pub fn transport_mut(&mut self) -> &mut T {
+        self.channel.get_mut()
+    }
+
+    /// 待機の手段への参照
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// セッション鍵が確立しているか
+    pub fn is_secure(&self) -> bool {
+        self.session.crypto.is_some()
+    }
+
+    /// 起動通知を送信（フレーム化した平文レスポンス、ペイロードはファームウェアの機能情報）
+    pub fn announce(&mut self) {
+        let response = Response::event("ESP32 ready for commands").with_payload(json!(self.info));
+        self.send_response(response, false);
+    }
+
+    /// パケットを1つ受信して処理
+    ///
+    /// 受信データがない場合は少し待機して戻ります。
+    pub fn poll(&mut self) -> Poll {
+        let poll = match self.channel.recv_packet() {
+            Ok(packet) => self.process_packet(packet),
+            Err(ChannelError::Closed) | Err(ChannelError::Timeout) => {
+                // EOF / WouldBlock は正常（ノンブロッキング読み取り）、少し待機してリトライ
+                self.clock.delay_ms(10);
+                return Poll::Continue;
+            }
+            Err(e) => {
+                log::error!("❌ UART read error: {}", e);
+                self.send_response(Response::error(ErrorCode::TransportError, "UART read error occurred"), false);
+                self.clock.delay_ms(10);
+                return Poll::Continue;
+            }
+        };
+
+        for bytes in self.channel.take_discarded() {
+            log::warn!("🗑️ Discarded {} bytes of non-frame data", bytes.len());
+        }
+
+        poll
+    }
+
+    /// レスポンス送信関数
+    ///
+    /// `encrypted` の場合は現在の鍵（セッション確立前はPSK）で暗号化して送信します。
+    ///
+    /// 応答がフレームに収まらない場合は、代わりに `Internal` エラーを返します。
+    fn send_response(&mut self, response: Response, encrypted: bool) {
+        let result = match self.send(&response, encrypted) {
+            Err(e @ ChannelError::FrameTooLarge { .. }) => {
+                log::error!("❌ Response to {:?} dropped: {}", response.response_to, e);
+                let mut fallback = Response::error(ErrorCode::Internal, "Response too large");
+                fallback.response_to = response.response_to;
+                fallback.id = response.id;
+                self.send(&fallback, encrypted)
+            }
+            result => result,
+        };
+        if let Err(e) = result {
+            log::error!("❌ Failed to send response: {}", e);
+        }
+    }
+
+    fn send(&mut self, response: &Response, encrypted: bool) -> Result<(), ChannelError> {
+        if encrypted {
+            self.channel.send(response)
+        } else {
+            self.channel.send_packet(&Packet::Response(response.clone()))
+        }
+    }
+
+    /// 受信したパケットを処理
+    ///
+    /// 暗号化されたコマンドには暗号化して、平文のコマンドには平文で応答します。
+    fn process_packet(&mut self, packet: Packet) -> Poll {
+        match packet {
+            Packet::Handshake(hello) => self.process_handshake(&hello),
+            Packet::Command(command) if self.require_encryption => {
+                log::warn!("🔒 Plaintext command rejected: {}", command.action);
+                self.send_response(Response::error(ErrorCode::EncryptionRequired, "Encryption required").in_reply_to(&command), false);
+            }
+            Packet::Command(command) => return self.process_command(&command, false),
+            Packet::Encrypted(encrypted) if self.require_encryption && !self.is_secure() => {
+                log::warn!("🔒 PSK-encrypted command rejected (seq={})", encrypted.seq);
+                self.send_response(Response::error(ErrorCode::EncryptionRequired, "Session key required"), false);
+            }
+            Packet::Encrypted(encrypted) => match self.channel.open::<Command>(&encrypted) {
+                Ok(command) => {
+                    log::info!("🔓 Encrypted command decrypted (seq={})", encrypted.seq);
+                    return self.process_command(&command, true);
+                }
+                Err(e) => {
+                    // 復号化できない相手には暗号化しても読めないため、平文で応答する
+                    log::warn!("❌ Failed to decrypt command (seq={}): {}", encrypted.seq, e);
+                    self.send_response(Response::error(ErrorCode::DecryptionFailed, "Decryption failed"), false);
+                }
+            },
+            Packet::Response(response) => {
+                log::warn!("❓ Unexpected response from host: {}", response.status);
+            }
+        }
+        Poll::Continue
+    }
+
+    /// 受信したコマンドを処理
+    ///
+    /// 権限を検査してからミドルウェアを通して登録されたハンドラに渡し、応答にアクション名とリクエストIDを付けて送信します。
+    /// `encrypted` は応答を暗号化するか（受信したコマンドが暗号化されていたか）です。
+    fn process_command(&mut self, command: &Command, encrypted: bool) -> Poll {
+        let (command_info, handler) = match self.registry.get_mut(&command.action) {
+            Some((info, handler)) => (Some(info), Some(handler)),
+            None => (None, None),
+        };
+        let cipher_suite = self.session.crypto.as_ref().map(|crypto| crypto.cipher_suite());
+        let mut context = Context::new(&self.info, self.system.as_ref(), &self.clock, command_info, cipher_suite, encrypted);
+        // 権限はミドルウェアの構成によらず検査する（ミドルウェアはログや制限などの方針のみを担う）
+        let result = context.authorize().and_then(|()| self.middleware.run(command, &mut context, handler));
+        let restart_after_ms = context.restart_after_ms();
+
+        // エラーを変換するミドルウェアがない場合もエラーレスポンスとして送信する
+        let response = result.unwrap_or_else(Response::from);
+        self.send_response(response.in_reply_to(command), encrypted);
+
+        match restart_after_ms {
+            Some(delay_ms) => {
+                self.clock.delay_ms(delay_ms);
+                Poll::Restart
+            }
+            None => Poll::Continue,
+        }
+    }
+
+    /// ホストからのハンドシェイクHelloに応答し、セッション鍵を確立
+    fn process_handshake(&mut self, hello: &HandshakeMessage) {
+        log::info!("🤝 Processing handshake hello");
+
+        match Handshake::respond(&self.session.psks, hello, &self.info.cipher_suites, &WireCodec::ALL) {
+            Ok((reply, crypto)) => {
+                // 応答はPSKのチャネルのまま送信し、その後セッション鍵に切り替える
+                if let Err(e) = self.channel.send_packet(&Packet::Handshake(reply)) {
+                    log::error!("❌ Failed to send handshake reply: {}", e);
+                }
+                log::info!("🔐 Session key established ({:?})", crypto.cipher_suite());
+                // セッション鍵は接続ごとに新しいため、RNGの品質に依存しないカウンタ方式のnonceを使う
+                let crypto = crypto.with_nonce_strategy(NonceStrategy::Counter)
+                    .expect("handshake always yields a session key");
+                self.channel.set_crypto(crypto.clone());
+                self.session.crypto = Some(crypto);
+            }
+            Err(e) => {
+                // 認証できないHelloで既存のセッションを切らせない
+                log::error!("❌ Handshake failed: {}", e);
+                self.send_response(Response::error(ErrorCode::HandshakeFailed, "Handshake failed"), false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{CommandError, CommandHandler};
+    use esp32_tauri_crypto::{CommandInfo, Privilege, Request, ResponseStatus, Role};
+    use std::collections::VecDeque;
+
+    /// 受信データをあとから追加できるメモリ上の入出力
+    #[derive(Default)]
+    struct MemoryTransport {
+        rx: VecDeque<u8>,
+        tx: Vec<u8>,
+    }
+
+    impl Read for MemoryTransport {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for MemoryTransport {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 待機せずに待機時間だけを記録し、その分だけ時刻を進める時計
+    #[derive(Default)]
+    struct RecordingClock {
+        delays: Vec<u32>,
+        now: Duration,
+    }
+
+    impl Clock for RecordingClock {
+        fn delay_ms(&mut self, ms: u32) {
+            self.delays.push(ms);
+            self.now += Duration::from_millis(ms.into());
+        }
+
+        fn now(&self) -> Duration {
+            self.now
+        }
+    }
+
+    /// ホスト側のチャネル（送信データの作成と応答の読み取り用）
+    fn host_channel(bytes: Vec<u8>) -> SecureChannel<MemoryTransport> {
+        let transport = MemoryTransport { rx: bytes.into(), tx: Vec::new() };
+        SecureChannel::new(transport, CryptoSystem::from_key([1u8; 32], Role::Host))
+    }
+
+    fn firmware() -> Firmware<MemoryTransport, RecordingClock> {
+        Firmware::new(MemoryTransport::default(), RecordingClock::default(), CryptoSystem::from_key([1u8; 32], Role::Device))
+    }
+
+    /// ホストからコマンドを送り、ファームウェアが1回受信処理した結果と応答を返す
+    fn exchange(firmware: &mut Firmware<MemoryTransport, RecordingClock>, command: Command) -> (Poll, Vec<Response>) {
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Command(command)).unwrap();
+        firmware.transport_mut().rx.extend(host.into_inner().tx);
+
+        let poll = firmware.poll();
+        (poll, take_responses(firmware))
+    }
+
+    /// ハンドシェイクを行い、セッション鍵を設定したホスト側のチャネルを返す
+    fn establish_session(firmware: &mut Firmware<MemoryTransport, RecordingClock>) -> SecureChannel<MemoryTransport> {
+        let psk = CryptoSystem::from_key([1u8; 32], Role::Host);
+        let (handshake, hello) = Handshake::initiate(&psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        firmware.poll();
+
+        host.get_mut().rx.extend(std::mem::take(&mut firmware.transport_mut().tx));
+        let reply = match host.recv_packet().unwrap() {
+            Packet::Handshake(reply) => reply,
+            other => panic!("unexpected packet: {:?}", other),
+        };
+        host.set_crypto(handshake.finish(&reply).unwrap());
+        host
+    }
+
+    /// 確立したセッションで暗号化コマンドを送り、暗号化された応答を返す
+    fn send_encrypted(firmware: &mut Firmware<MemoryTransport, RecordingClock>, host: &mut SecureChannel<MemoryTransport>, command: Command) -> Response {
+        host.send(&command).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        firmware.poll();
+        host.get_mut().rx.extend(std::mem::take(&mut firmware.transport_mut().tx));
+        host.recv().unwrap()
+    }
+
+    fn take_responses(firmware: &mut Firmware<MemoryTransport, RecordingClock>) -> Vec<Response> {
+        let mut host = host_channel(std::mem::take(&mut firmware.transport_mut().tx));
+        let mut responses = Vec::new();
+        while let Ok(packet) = host.recv_packet() {
+            match packet {
+                Packet::Response(response) => responses.push(response),
+                other => panic!("unexpected packet: {:?}", other),
+            }
+        }
+        responses
+    }
+
+    #[test]
+    fn test_announce_reports_device_info() {
+        let mut firmware = firmware();
+        firmware.announce();
+
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].status, ResponseStatus::Event);
+        let info: DeviceInfo = serde_json::from_value(responses[0].payload.clone().unwrap()).unwrap();
+        assert_eq!(info, device_info().with_commands(CommandRegistry::builtin().commands()));
+    }
+
+    #[test]
+    fn test_replies_carry_request_id() {
+        let mut firmware = firmware();
+
+        let (poll, responses) = exchange(&mut firmware, Command::new("ping").with_id(7));
+        assert_eq!(poll, Poll::Continue);
+        assert_eq!(responses[0].status, ResponseStatus::Ok);
+        assert_eq!(responses[0].id, Some(7));
+        assert_eq!(responses[0].response_to.as_deref(), Some("ping"));
+
+        let (_, responses) = exchange(&mut firmware, Command::new("dance").with_id(8));
+        assert_eq!(responses[0].code, Some(ErrorCode::UnknownCommand));
+        assert_eq!(responses[0].id, Some(8));
+
+        let (_, responses) = exchange(&mut firmware, Command::new("echo"));
+        assert_eq!(responses[0].code, Some(ErrorCode::InvalidParameters));
+    }
+
+    #[test]
+    fn test_encrypted_command_gets_encrypted_reply() {
+        let mut firmware = firmware().with_require_encryption(true);
+
+        // 平文は拒否される
+        let (_, responses) = exchange(&mut firmware, Command::new("ping").with_id(4));
+        assert_eq!(responses[0].code, Some(ErrorCode::EncryptionRequired));
+        assert_eq!(responses[0].id, Some(4));
+
+        // PSKでの暗号化は再起動後にリプレイできるため拒否される
+        let mut host = host_channel(Vec::new());
+        host.send(&Command::new("ping").with_id(5)).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        assert_eq!(firmware.poll(), Poll::Continue);
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses[0].code, Some(ErrorCode::EncryptionRequired));
+
+        let mut host = establish_session(&mut firmware);
+        let response = send_encrypted(&mut firmware, &mut host, Command::new("ping").with_id(6));
+        assert_eq!(response.status, ResponseStatus::Ok);
+        assert_eq!(response.id, Some(6));
+    }
+
+    #[test]
+    fn test_undecryptable_command_is_reported_in_plaintext() {
+        let mut firmware = firmware();
+        let transport = MemoryTransport::default();
+        let mut stranger = SecureChannel::new(transport, CryptoSystem::from_key([2u8; 32], Role::Host));
+        stranger.send(&Command::new("ping")).unwrap();
+        firmware.transport_mut().rx.extend(stranger.into_inner().tx);
+        firmware.poll();
+
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses[0].code, Some(ErrorCode::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_registered_handler_is_reported_and_privilege_enforced() {
+        struct Unlock;
+
+        impl CommandHandler for Unlock {
+            fn info(&self) -> CommandInfo {
+                CommandInfo::new("unlock", "Unlock the door").with_privilege(Privilege::Authenticated)
+            }
+
+            fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+                Ok(Response::ok("🔓 Unlocked"))
+            }
+        }
+
+        let registry = CommandRegistry::new().with_handler(Unlock);
+        let mut firmware = firmware().with_registry(registry);
+        firmware.announce();
+        let info: DeviceInfo = serde_json::from_value(take_responses(&mut firmware)[0].payload.clone().unwrap()).unwrap();
+        assert_eq!(info.commands, vec!["unlock".to_string()]);
+        assert_eq!(info.command("unlock").unwrap().privilege, Privilege::Authenticated);
+
+        let (_, responses) = exchange(&mut firmware, Command::new("unlock").with_id(1));
+        assert_eq!(responses[0].code, Some(ErrorCode::PermissionDenied));
+        let (_, responses) = exchange(&mut firmware, Command::new("ping").with_id(2));
+        assert_eq!(responses[0].code, Some(ErrorCode::UnknownCommand));
+
+        let mut host = host_channel(Vec::new());
+        host.send(&Command::new("unlock").with_id(3)).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        firmware.poll();
+        host.get_mut().rx.extend(std::mem::take(&mut firmware.transport_mut().tx));
+        let response: Response = host.recv().unwrap();
+        assert_eq!(response.status, ResponseStatus::Ok);
+        assert_eq!(response.id, Some(3));
+    }
+
+    #[test]
+    fn test_oversized_response_is_replaced_with_error() {
+        struct Dump;
+
+        impl CommandHandler for Dump {
+            fn info(&self) -> CommandInfo {
+                CommandInfo::new("dump", "Dump everything")
+            }
+
+            fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+                Ok(Response::ok("dump").with_payload(json!("x".repeat(8192))))
+            }
+        }
+
+        let mut firmware = firmware().with_registry(CommandRegistry::new().with_handler(Dump));
+        let (_, responses) = exchange(&mut firmware, Command::new("dump").with_id(9));
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].code, Some(ErrorCode::Internal));
+        assert_eq!(responses[0].id, Some(9));
+        assert_eq!(responses[0].response_to.as_deref(), Some("dump"));
+    }
+
+    #[test]
+    fn test_reboot_waits_then_requests_restart() {
+        let mut firmware = firmware();
+        let command = Command::from(&Request::Reboot { delay_ms: 250 });
+
+        // セッション鍵が必要
+        let (poll, responses) = exchange(&mut firmware, command.clone());
+        assert_eq!(poll, Poll::Continue);
+        assert_eq!(responses[0].code, Some(ErrorCode::PermissionDenied));
+
+        let mut host = establish_session(&mut firmware);
+        host.send(&command).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        assert_eq!(firmware.poll(), Poll::Restart);
+        host.get_mut().rx.extend(std::mem::take(&mut firmware.transport_mut().tx));
+        let response: Response = host.recv().unwrap();
+        assert_eq!(response.payload, Some(json!({ "delay_ms": 250 })));
+        assert_eq!(firmware.clock().delays, vec![250]);
+    }
+
+    #[test]
+    fn test_privilege_enforced_without_authorization_middleware() {
+        let mut firmware = firmware().with_middleware(MiddlewareStack::new());
+        let command = Command::from(&Request::Reboot { delay_ms: 0 }).with_id(1);
+
+        let (poll, responses) = exchange(&mut firmware, command.clone());
+        assert_eq!(poll, Poll::Continue);
+        assert_eq!(responses[0].code, Some(ErrorCode::PermissionDenied));
+
+        // PSKで暗号化していてもセッション鍵がなければ拒否される
+        let mut host = host_channel(Vec::new());
+        host.send(&command).unwrap();
+        firmware.transport_mut().rx.extend(std::mem::take(&mut host.get_mut().tx));
+        assert_eq!(firmware.poll(), Poll::Continue);
+        host.get_mut().rx.extend(std::mem::take(&mut firmware.transport_mut().tx));
+        let response: Response = host.recv().unwrap();
+        assert_eq!(response.code, Some(ErrorCode::PermissionDenied));
+        assert!(firmware.clock().delays.is_empty());
+    }
+
+    #[test]
+    fn test_idle_poll_waits() {
+        let mut firmware = firmware();
+        assert_eq!(firmware.poll(), Poll::Continue);
+        assert_eq!(firmware.clock().delays, vec![10]);
+        assert!(firmware.transport().tx.is_empty());
+    }
+
+    #[test]
+    fn test_handshake_establishes_session() {
+        let mut firmware = firmware();
+        let psk = CryptoSystem::from_key([1u8; 32], Role::Host);
+        let (handshake, hello) = Handshake::initiate(&psk, &CipherSuite::ALL, &WireCodec::ALL);
+
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(host.into_inner().tx);
+        assert_eq!(firmware.poll(), Poll::Continue);
+        assert!(firmware.is_secure());
+
+        let mut host = host_channel(std::mem::take(&mut firmware.transport_mut().tx));
+        let reply = match host.recv_packet().unwrap() {
+            Packet::Handshake(reply) => reply,
+            other => panic!("unexpected packet: {:?}", other),
+        };
+        let crypto = handshake.finish(&reply).unwrap();
+        assert_eq!(crypto.cipher_suite(), CipherSuite::ChaCha20Poly1305);
+        assert_eq!(crypto.codec(), WireCodec::Cbor);
+    }
+
+    #[test]
+    fn test_without_cipher_suites_handshake_is_refused() {
+        let mut firmware = firmware().with_cipher_suites(&[]);
+        let psk = CryptoSystem::from_key([1u8; 32], Role::Host);
+        let (_, hello) = Handshake::initiate(&psk, &CipherSuite::ALL, &WireCodec::ALL);
+
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(host.into_inner().tx);
+        firmware.poll();
+
+        assert!(!firmware.is_secure());
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses[0].code, Some(ErrorCode::HandshakeFailed));
+    }
+
+    #[test]
+    fn test_handshake_accepts_any_psk_in_keyring() {
+        let mut psks = Keyring::new(CryptoSystem::from_key([1u8; 32], Role::Device));
+        psks.insert(CryptoSystem::from_key([3u8; 32], Role::Device).with_key_id(2));
+        let mut firmware = firmware().with_keyring(psks);
+
+        let psk = CryptoSystem::from_key([3u8; 32], Role::Host).with_key_id(2);
+        let (_, hello) = Handshake::initiate(&psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(host.into_inner().tx);
+        firmware.poll();
+        assert!(firmware.is_secure());
+
+        let retired = CryptoSystem::from_key([4u8; 32], Role::Host).with_key_id(1);
+        let (_, hello) = Handshake::initiate(&retired, &CipherSuite::ALL, &WireCodec::ALL);
+        let mut host = host_channel(Vec::new());
+        host.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(host.into_inner().tx);
+        std::mem::take(&mut firmware.transport_mut().tx);
+        firmware.poll();
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses[0].code, Some(ErrorCode::HandshakeFailed));
+    }
+
+    #[test]
+    fn test_sdkconfig_disables_console_line_ending_conversion() {
+        let sdkconfig = include_str!("../sdkconfig.defaults");
+        for option in ["CONFIG_NEWLIB_STDOUT_LINE_ENDING_LF=y", "CONFIG_NEWLIB_STDIN_LINE_ENDING_LF=y"] {
+            assert!(sdkconfig.lines().any(|line| line.trim() == option), "{} is missing", option);
+        }
+    }
+
+    #[test]
+    fn test_failed_handshake_keeps_session() {
+        let mut firmware = firmware().with_require_encryption(true);
+        let mut host = establish_session(&mut firmware);
+
+        let attacker = CryptoSystem::from_key([2u8; 32], Role::Host);
+        let (_, hello) = Handshake::initiate(&attacker, &CipherSuite::ALL, &WireCodec::ALL);
+        let mut stranger = host_channel(Vec::new());
+        stranger.send_packet(&Packet::Handshake(hello)).unwrap();
+        firmware.transport_mut().rx.extend(stranger.into_inner().tx);
+        firmware.poll();
+
+        assert!(firmware.is_secure());
+        let responses = take_responses(&mut firmware);
+        assert_eq!(responses[0].code, Some(ErrorCode::HandshakeFailed));
+
+        let response = send_encrypted(&mut firmware, &mut host, Command::new("ping").with_id(1));
+        assert_eq!(response.status, ResponseStatus::Ok);
+    }
+}
@@ -1,7 +1,11 @@
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::sys::link_patches;
+#[cfg(target_os = "espidf")]
 use std::thread;
+#[cfg(target_os = "espidf")]
 use backend::run_communication_loop;
 
+#[cfg(target_os = "espidf")]
 fn main() {
     link_patches();
 
@@ -15,3 +19,8 @@ fn main() {
         .unwrap();
 }
 
+/// ホスト向けのビルドではファームウェアは動かさない（プロトコル処理は `cargo test -p backend` で確認する）
+#[cfg(not(target_os = "espidf"))]
+fn main() {
+    eprintln!("backend is ESP32 firmware; run `cargo test -p backend` to test the protocol on the host");
+}
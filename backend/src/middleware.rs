@@ -0,0 +1,368 @@
+//! # コマンド処理のミドルウェア
+//!
+//! ログ・処理時間の計測・権限チェック・レート制限・エラーの変換など、
+//! コマンドに共通する処理を [`Middleware`] としてハンドラの外側に重ねます。
+//! [`MiddlewareStack`] に先に追加したものほど外側で実行されます。
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use backend::middleware::{Authorization, ErrorMapping, MiddlewareStack, RateLimit, RequestLog};
+//!
+//! let middleware = MiddlewareStack::new()
+//!     .with(ErrorMapping)
+//!     .with(RequestLog)
+//!     .with(RateLimit::new(5, Duration::from_secs(1)))
+//!     .with(Authorization);
+//! assert_eq!(middleware.len(), 4);
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use esp32_tauri_crypto::{Command, ErrorCode, Response};
+
+use crate::commands::{CommandError, CommandHandler, Context};
+
+/// ハンドラの前後に処理を挟むミドルウェア
+pub trait Middleware {
+    /// コマンドを処理
+    ///
+    /// `next.run(command, context)` で内側のミドルウェアとハンドラを実行します。
+    /// 呼ばずに戻るとコマンドはハンドラに届きません。
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError>;
+}
+
+/// 内側のミドルウェアとハンドラ
+pub struct Next<'a> {
+    layers: &'a mut [Box<dyn Middleware>],
+    handler: Option<&'a mut (dyn CommandHandler + 'static)>,
+}
+
+impl Next<'_> {
+    /// 内側のミドルウェアとハンドラを実行
+    ///
+    /// # エラー
+    /// ハンドラが登録されていないアクションは `ErrorCode::UnknownCommand`
+    pub fn run(self, command: &Command, context: &mut Context) -> Result<Response, CommandError> {
+        match self.layers.split_first_mut() {
+            Some((layer, layers)) => layer.handle(command, context, Next { layers, handler: self.handler }),
+            None => match self.handler {
+                Some(handler) => handler.handle(command, context),
+                None => Err(CommandError::new(ErrorCode::UnknownCommand, "Unknown command")),
+            },
+        }
+    }
+}
+
+/// ミドルウェアの並び（先に追加したものほど外側）
+#[derive(Default)]
+pub struct MiddlewareStack {
+    layers: Vec<Box<dyn Middleware>>,
+}
+
+impl MiddlewareStack {
+    /// 空のスタックを作成
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 標準の構成
+    ///
+    /// 外側から、エラーの変換・ログ・処理時間の計測（100ms超で警告）・
+    /// レート制限（1秒あたり20コマンド）・権限チェックの順に実行します。
+    pub fn standard() -> Self {
+        Self::new()
+            .with(ErrorMapping)
+            .with(RequestLog)
+            .with(Timing::new().with_warn_threshold(Duration::from_millis(100)))
+            .with(RateLimit::new(20, Duration::from_secs(1)))
+            .with(Authorization)
+    }
+
+    /// ミドルウェアを内側に追加
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.layers.push(Box::new(middleware));
+    }
+
+    /// ミドルウェアを内側に追加したスタックを返す
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.push(middleware);
+        self
+    }
+
+    /// ミドルウェアの数
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// ミドルウェアがないか
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// すべてのミドルウェアを通してハンドラを実行
+    ///
+    /// # 引数
+    /// * `handler` - アクションに対応するハンドラ（未登録の場合は `None`）
+    pub fn run(
+        &mut self,
+        command: &Command,
+        context: &mut Context,
+        handler: Option<&mut (dyn CommandHandler + 'static)>,
+    ) -> Result<Response, CommandError> {
+        Next { layers: &mut self.layers, handler }.run(command, context)
+    }
+}
+
+/// エラーをエラーレスポンスに変換してログに残す
+///
+/// 最も外側に置くと、内側のミドルウェアが返したエラーも変換されます。
+pub struct ErrorMapping;
+
+impl Middleware for ErrorMapping {
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+        Ok(next.run(command, context).unwrap_or_else(|error| {
+            log::warn!("⚠️ Command '{}' failed: {} ({})", command.action, error.message, error.code);
+            error.into()
+        }))
+    }
+}
+
+/// 受信したコマンドと応答のステータスをログに残す
+pub struct RequestLog;
+
+impl Middleware for RequestLog {
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+        // デバッグ情報はログのみに出力（シリアルには送信しない）
+        log::info!(
+            "📨 Processing command: action='{}', data={:?}, params={:?}, binary={} bytes, id={:?}, encrypted={}",
+            command.action,
+            command.data,
+            command.params,
+            command.binary.as_ref().map_or(0, |binary| binary.len()),
+            command.id,
+            context.is_encrypted()
+        );
+
+        let result = next.run(command, context);
+        match &result {
+            Ok(response) => log::info!("📤 Replying to '{}': {}", command.action, response.status),
+            Err(error) => log::info!("📤 Replying to '{}': error ({})", command.action, error.code),
+        }
+        result
+    }
+}
+
+/// ハンドラの処理時間を計測してログに残す
+///
+/// 時刻はファームウェアの時計（[`Context::now`]）から取得します。
+#[derive(Default)]
+pub struct Timing {
+    /// これを超えた場合は警告する
+    warn_threshold: Option<Duration>,
+}
+
+impl Timing {
+    /// 新しい計測を作成（警告なし）
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 処理時間がこれを超えたら警告する
+    pub fn with_warn_threshold(mut self, threshold: Duration) -> Self {
+        self.warn_threshold = Some(threshold);
+        self
+    }
+}
+
+impl Middleware for Timing {
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+        let started = context.now();
+        let result = next.run(command, context);
+        let elapsed = context.now().saturating_sub(started);
+
+        match self.warn_threshold {
+            Some(threshold) if elapsed > threshold => {
+                log::warn!("🐢 Command '{}' took {} ms", command.action, elapsed.as_millis());
+            }
+            _ => log::info!("⏱️ Command '{}' took {} µs", command.action, elapsed.as_micros()),
+        }
+        result
+    }
+}
+
+/// 一定時間あたりのコマンド数を制限する
+///
+/// 制限を超えたコマンドはハンドラに渡さず、`ErrorCode::RateLimited` で応答します。
+/// 時刻はファームウェアの時計（[`Context::now`]）から取得します。
+pub struct RateLimit {
+    max_commands: usize,
+    window: Duration,
+    /// 受け付けたコマンドの時刻（起動からの経過時間、古い順）
+    accepted: VecDeque<Duration>,
+}
+
+impl RateLimit {
+    /// 新しいレート制限を作成
+    ///
+    /// # 引数
+    /// * `max_commands` - `window` の間に受け付けるコマンド数
+    /// * `window` - 集計する時間
+    pub fn new(max_commands: usize, window: Duration) -> Self {
+        Self { max_commands, window, accepted: VecDeque::with_capacity(max_commands) }
+    }
+
+    /// 時刻 `now` にコマンドを受け付けられるか（受け付ける場合は記録する）
+    fn try_accept(&mut self, now: Duration) -> bool {
+        while self.accepted.front().is_some_and(|accepted| now.saturating_sub(*accepted) >= self.window) {
+            self.accepted.pop_front();
+        }
+        if self.accepted.len() >= self.max_commands {
+            return false;
+        }
+        self.accepted.push_back(now);
+        true
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+        if !self.try_accept(context.now()) {
+            return Err(CommandError::new(ErrorCode::RateLimited, "Too many commands"));
+        }
+        next.run(command, context)
+    }
+}
+
+/// コマンドの説明（`CommandInfo::privilege`）に従って実行を許可する
+///
+/// 平文のコマンドや、セッション確立前のコマンドを `ErrorCode::PermissionDenied` で拒否します。
+/// `Firmware` はミドルウェアより先に同じ検査（[`Context::authorize`]）を行うため、
+/// スタックから外しても権限のないコマンドは実行されません。
+pub struct Authorization;
+
+impl Middleware for Authorization {
+    fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+        context.authorize()?;
+        next.run(command, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use esp32_tauri_crypto::{CommandInfo, DeviceInfo, Privilege, ResponseStatus};
+
+    use crate::firmware::Clock;
+    use crate::system::HostSystem;
+
+    /// テストから時刻を進める時計
+    #[derive(Default)]
+    struct ManualClock(Cell<Duration>);
+
+    impl ManualClock {
+        fn advance(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn delay_ms(&mut self, ms: u32) {
+            self.advance(Duration::from_millis(ms.into()));
+        }
+
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    /// 呼ばれた順序を記録するミドルウェア
+    struct Record(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl Middleware for Record {
+        fn handle(&mut self, command: &Command, context: &mut Context, next: Next) -> Result<Response, CommandError> {
+            self.1.borrow_mut().push(self.0);
+            next.run(command, context)
+        }
+    }
+
+    struct SessionOnly;
+
+    impl CommandHandler for SessionOnly {
+        fn info(&self) -> CommandInfo {
+            CommandInfo::new("ok", "Always succeeds").with_privilege(Privilege::Session)
+        }
+
+        fn handle(&mut self, _command: &Command, _context: &mut Context) -> Result<Response, CommandError> {
+            Ok(Response::ok("ok"))
+        }
+    }
+
+    fn device_info() -> DeviceInfo {
+        DeviceInfo::new("0.0.0", "test", &[])
+    }
+
+    #[test]
+    fn test_layers_run_outside_in() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut stack = MiddlewareStack::new()
+            .with(Record("outer", calls.clone()))
+            .with(Record("inner", calls.clone()));
+
+        let info = device_info();
+        let system = HostSystem::new();
+        let clock = ManualClock::default();
+        let mut context = Context::new(&info, &system, &clock, None, None, false);
+        let result = stack.run(&Command::new("missing"), &mut context, None);
+
+        assert_eq!(*calls.borrow(), vec!["outer", "inner"]);
+        assert_eq!(result.unwrap_err().code, ErrorCode::UnknownCommand);
+    }
+
+    #[test]
+    fn test_authorization_and_error_mapping() {
+        let mut stack = MiddlewareStack::new().with(ErrorMapping).with(Authorization);
+        let mut handler = SessionOnly;
+        let command_info = handler.info();
+        let info = device_info();
+        let system = HostSystem::new();
+        let clock = ManualClock::default();
+
+        // セッション鍵で暗号化されていないコマンドは拒否され、エラーレスポンスに変換される
+        let mut context = Context::new(&info, &system, &clock, Some(&command_info), None, true);
+        let response = stack.run(&Command::new("ok"), &mut context, Some(&mut handler)).unwrap();
+        assert_eq!(response.status, ResponseStatus::Error);
+        assert_eq!(response.code, Some(ErrorCode::PermissionDenied));
+
+        let mut context = Context::new(&info, &system, &clock, Some(&command_info), Some(esp32_tauri_crypto::CipherSuite::ChaCha20Poly1305), true);
+        let response = stack.run(&Command::new("ok"), &mut context, Some(&mut handler)).unwrap();
+        assert_eq!(response.status, ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let mut stack = MiddlewareStack::new().with(RateLimit::new(2, Duration::from_secs(1)));
+        let mut handler = SessionOnly;
+        let info = device_info();
+        let system = HostSystem::new();
+        let clock = ManualClock::default();
+        let mut run = |clock: &ManualClock| {
+            let mut context = Context::new(&info, &system, clock, None, None, false);
+            stack.run(&Command::new("ok"), &mut context, Some(&mut handler)).map(|_| ()).map_err(|e| e.code)
+        };
+
+        assert_eq!(run(&clock), Ok(()));
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(run(&clock), Ok(()));
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(run(&clock), Err(ErrorCode::RateLimited));
+        // 最初のコマンドから1秒経つと1つ空く
+        clock.advance(Duration::from_millis(980));
+        assert_eq!(run(&clock), Ok(()));
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(run(&clock), Err(ErrorCode::RateLimited));
+    }
+}
@@ -0,0 +1,83 @@
+//! # デバイスの稼働状態の取得
+//!
+//! `status` コマンドが報告するヒープ・稼働時間・リセット要因などを [`SystemInfo`] で取得します。
+//! 実機では `EspSystem`（ESP-IDFのAPI）、ホストのテストやシミュレータでは [`HostSystem`] を使います。
+
+use std::time::Instant;
+
+use esp32_tauri_crypto::{ChipInfo, HeapStats, ResetReason, TaskStack};
+
+/// デバイスの稼働状態
+pub trait SystemInfo {
+    /// ヒープの使用状況
+    fn heap(&self) -> HeapStats;
+
+    /// 起動からの経過時間（ミリ秒）
+    fn uptime_ms(&self) -> u64;
+
+    /// 直前のリセット要因
+    fn reset_reason(&self) -> ResetReason;
+
+    /// チップの情報
+    fn chip(&self) -> ChipInfo;
+
+    /// ESP-IDFのバージョン
+    fn idf_version(&self) -> String;
+
+    /// タスクごとのスタックの余裕
+    fn task_stacks(&self) -> Vec<TaskStack>;
+}
+
+/// ホストで動かすときの稼働状態
+///
+/// 稼働時間は作成してからの経過時間です。ヒープやスタックは取得できないため0を報告します。
+pub struct HostSystem {
+    started: Instant,
+    reset_reason: ResetReason,
+}
+
+impl HostSystem {
+    /// 現在時刻を起動時刻とする（リセット要因は電源投入）
+    pub fn new() -> Self {
+        Self { started: Instant::now(), reset_reason: ResetReason::PowerOn }
+    }
+
+    /// 報告するリセット要因を変更
+    pub fn with_reset_reason(mut self, reason: ResetReason) -> Self {
+        self.reset_reason = reason;
+        self
+    }
+}
+
+impl Default for HostSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemInfo for HostSystem {
+    fn heap(&self) -> HeapStats {
+        HeapStats { free_bytes: 0, min_free_bytes: 0 }
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn reset_reason(&self) -> ResetReason {
+        self.reset_reason
+    }
+
+    fn chip(&self) -> ChipInfo {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get().min(u8::MAX.into()) as u8);
+        ChipInfo { model: "host".to_string(), revision: 0, cores }
+    }
+
+    fn idf_version(&self) -> String {
+        "host".to_string()
+    }
+
+    fn task_stacks(&self) -> Vec<TaskStack> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,184 @@
+//! シミュレータを起動し、GUIと同じ手順（ハンドシェイク → 暗号化コマンド）でptyを通して通信するテスト
+
+#![cfg(all(unix, not(target_os = "espidf")))]
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command as Process, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use esp32_tauri_crypto::channel::{Packet, SecureChannel};
+use esp32_tauri_crypto::handshake::Handshake;
+use esp32_tauri_crypto::{
+    CipherSuite, Command, CryptoSystem, DeviceStatus, ErrorCode, KDF_PARAMS_ENV, PASSPHRASE_ENV, ResetReason, Request, Response,
+    ResponseStatus, Role, WireCodec, create_default_crypto,
+};
+
+/// 応答を待つ最大時間
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ptyのスレーブ側（読み取りは別スレッドで行い、タイムアウト付きで受け取る）
+struct PtyTransport {
+    port: File,
+    incoming: Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl Read for PtyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            self.buffer = self.incoming.recv_timeout(RECV_TIMEOUT).map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?;
+        }
+        let len = buf.len().min(self.buffer.len());
+        buf[..len].copy_from_slice(&self.buffer[..len]);
+        self.buffer.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Write for PtyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+/// 起動したシミュレータと、それに接続したホスト側のチャネル
+struct Simulator {
+    process: Child,
+    channel: SecureChannel<PtyTransport>,
+    psk: CryptoSystem,
+    next_id: u32,
+}
+
+impl Simulator {
+    fn spawn() -> Self {
+        // テストはデモ用の固定鍵で通信する
+        let mut process = Process::new(env!("CARGO_BIN_EXE_simulator"))
+            .env_remove(PASSPHRASE_ENV)
+            .env_remove(KDF_PARAMS_ENV)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start the simulator");
+
+        // 1行目の末尾がptyのパス（以降の出力は読み捨てる）
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let path = line.trim().rsplit(' ').next().unwrap().to_string();
+        thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+
+        let port = OpenOptions::new().read(true).write(true).open(&path).expect("Failed to open the pseudo-terminal");
+        let mut reader = port.try_clone().unwrap();
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0u8; 256];
+            while let Ok(len @ 1..) = reader.read(&mut buf) {
+                if sender.send(buf[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let psk = create_default_crypto(Role::Host);
+        let transport = PtyTransport { port, incoming, buffer: Vec::new() };
+        Self { process, channel: SecureChannel::new(transport, psk.clone()), psk, next_id: 0 }
+    }
+
+    fn recv(&mut self) -> Packet {
+        self.channel.recv_packet().expect("No packet from the simulator")
+    }
+
+    /// 起動通知を待つ
+    fn wait_ready(&mut self) -> Response {
+        loop {
+            if let Packet::Response(response) = self.recv() {
+                if response.status == ResponseStatus::Event && response.response_to.is_none() {
+                    return response;
+                }
+            }
+        }
+    }
+
+    /// PSKに戻してハンドシェイクを行い、セッション鍵に切り替える
+    fn handshake(&mut self) {
+        self.channel.set_crypto(self.psk.clone());
+        let (handshake, hello) = Handshake::initiate(&self.psk, &CipherSuite::ALL, &WireCodec::ALL);
+        self.channel.send_packet(&Packet::Handshake(hello)).unwrap();
+        loop {
+            if let Packet::Handshake(reply) = self.recv() {
+                self.channel.set_crypto(handshake.finish(&reply).unwrap());
+                return;
+            }
+        }
+    }
+
+    /// 次の応答（暗号化されていれば復号する）
+    fn next_response(&mut self) -> Response {
+        loop {
+            match self.recv() {
+                Packet::Encrypted(message) => return self.channel.open(&message).unwrap(),
+                Packet::Response(response) => return response,
+                _ => {}
+            }
+        }
+    }
+
+    /// 現在の鍵でリクエストを暗号化して送る
+    fn send(&mut self, request: Request) -> u32 {
+        self.next_id += 1;
+        self.channel.send(&Command::from(request).with_id(self.next_id)).unwrap();
+        self.next_id
+    }
+
+    /// リクエストを送り、対応する応答を返す
+    fn request(&mut self, request: Request) -> Response {
+        let id = self.send(request);
+        loop {
+            let response = self.next_response();
+            if response.id == Some(id) {
+                return response;
+            }
+        }
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+#[test]
+fn test_reboot_then_command_over_new_session() {
+    let mut simulator = Simulator::spawn();
+    simulator.wait_ready();
+    simulator.handshake();
+
+    let response = simulator.request(Request::Reboot { delay_ms: 0 });
+    assert_eq!(response.status, ResponseStatus::Ok, "{}", response.message);
+
+    // 再起動後は以前のセッション鍵で暗号化したコマンドを復号できない（GUIはこのエラーでハンドシェイクをやり直す）
+    simulator.wait_ready();
+    simulator.send(Request::Ping);
+    let response = simulator.next_response();
+    assert_eq!(response.code, Some(ErrorCode::DecryptionFailed));
+    assert_eq!(response.response_to, None);
+
+    // ハンドシェイクをやり直せば、改行を含むデータも暗号化して送受信できる
+    simulator.handshake();
+    let text = "line 1\nline 2\r\n".to_string();
+    let response = simulator.request(Request::Echo { text: text.clone() });
+    assert_eq!(response.payload, Some(serde_json::json!({ "text": text })));
+
+    let response = simulator.request(Request::Status);
+    let status: DeviceStatus = serde_json::from_value(response.payload.unwrap()).unwrap();
+    assert_eq!(status.reset_reason, ResetReason::Software);
+}
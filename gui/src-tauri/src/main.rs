@@ -1,14 +1,26 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{sync::{Arc, OnceLock, Mutex}, time::Duration, thread, io::{BufRead, BufReader, Write}};
+use std::{collections::HashMap, sync::{Arc, OnceLock, Mutex}, time::{Duration, Instant}};
 use tauri::{Emitter, State};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
 
 // 共通暗号化ライブラリ
-use esp32_tauri_crypto::{CryptoSystem, EncryptedMessage, Command, Response, create_default_crypto};
+use esp32_tauri_crypto::{AsyncSecureChannel, CipherSuite, Compatibility, CryptoSystem, DeviceInfo, EncryptedMessage, ErrorCode, Command, Request, Response, ResponseStatus, Role, WireCodec, KDF_PARAMS_ENV, PASSPHRASE_ENV, create_default_crypto, create_provisioned_crypto};
+use esp32_tauri_crypto::channel::Packet;
+use esp32_tauri_crypto::handshake::Handshake;
+use esp32_tauri_crypto::transport::{self, ReconnectingSerial, SerialConfig, SerialStream};
 
-// シリアルポート管理用
-type SharedSerialPort = Arc<Mutex<Option<Box<dyn serialport::SerialPort>>>>;
+// 受信タスクに送信を依頼するコマンド
+struct Outgoing {
+    command: Command,
+    encrypted: bool,
+    /// 送信結果（付与したリクエストID）の通知先
+    sent: oneshot::Sender<Result<u32, String>>,
+}
+
+// 接続中の受信タスクへの送信口（未接続なら `None`）
+type SharedLink = Arc<Mutex<Option<mpsc::UnboundedSender<Outgoing>>>>;
 
 // シリアルポート関連の型
 #[derive(Debug)]
@@ -18,230 +30,550 @@ struct PortNameState(String);
 
 // 軽量暗号化関連の状態
 struct SimpleCryptoState {
+    /// 事前共有鍵（ハンドシェイクの認証用）
+    psk: CryptoSystem,
+    /// ハンドシェイクで確立したセッション鍵
     crypto_system: CryptoSystem,
+    /// セッションが確立済みか
     is_ready: bool,
 }
 
+type SharedCryptoState = Arc<Mutex<SimpleCryptoState>>;
+
+// 応答待ちのリクエスト（リクエストID → アクション名と送信時刻）
+#[derive(Default)]
+struct PendingRequests {
+    next_id: u32,
+    pending: HashMap<u32, (String, Instant)>,
+}
+
+impl PendingRequests {
+    /// 新しいリクエストIDを払い出して応答待ちに登録
+    fn register(&mut self, action: &str) -> u32 {
+        // 応答が返らなかった古いリクエストは破棄
+        self.pending.retain(|_, (_, sent_at)| sent_at.elapsed() < PENDING_REQUEST_TIMEOUT);
+
+        // 0 は使わない（IDなしの応答と区別しやすくするため）
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.pending.insert(self.next_id, (action.to_string(), Instant::now()));
+        self.next_id
+    }
+
+    /// 応答を受信したリクエストを取り出す（アクション名と往復時間）
+    fn complete(&mut self, id: u32) -> Option<(String, Duration)> {
+        self.pending.remove(&id).map(|(action, sent_at)| (action, sent_at.elapsed()))
+    }
+}
+
+type SharedPendingRequests = Arc<Mutex<PendingRequests>>;
+
+// 接続中のファームウェアの機能情報と互換性（"device-info" イベントのペイロード）
+#[derive(Debug, Clone, Default, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS))]
+struct DeviceState {
+    /// ファームウェアが報告した機能情報（旧ファームウェアでは `None`）
+    info: Option<DeviceInfo>,
+    /// ホストとの互換性（機能情報がなければ `None`）
+    compatibility: Option<Compatibility>,
+}
+
+impl DeviceState {
+    /// コマンドを送信できるか（機能情報がない旧ファームウェアには制限しない）
+    fn check_command(&self, action: &str, encrypted: bool) -> Result<(), String> {
+        let (Some(info), Some(compatibility)) = (&self.info, &self.compatibility) else {
+            return Ok(());
+        };
+        match compatibility {
+            Compatibility::Incompatible { host_protocol, device_protocol } => Err(format!(
+                "Firmware v{} uses protocol v{}, but this app requires v{}. Please update the firmware.",
+                info.firmware_version, device_protocol, host_protocol
+            )),
+            Compatibility::Degraded { encryption_available: false, .. } if encrypted => Err(format!(
+                "Firmware v{} has no cipher suite in common with this app",
+                info.firmware_version
+            )),
+            _ if !info.supports(action) => Err(format!(
+                "Command '{}' is not supported by firmware v{}",
+                action, info.firmware_version
+            )),
+            // 暗号化コマンドはセッション確立後にのみ送信する
+            _ => match info.command(action) {
+                Some(command) if !command.privilege.allows(encrypted, encrypted) => Err(format!(
+                    "Command '{}' requires {} privilege. Please send it encrypted.",
+                    action, command.privilege
+                )),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+type SharedDeviceState = Arc<Mutex<DeviceState>>;
+
 static START: OnceLock<()> = OnceLock::new();
 
+// 応答待ちのリクエストを破棄するまでの時間
+const PENDING_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// ハンドシェイクで提示する暗号スイート（最終的な選択はESP32側の優先順）
+const OFFERED_CIPHER_SUITES: [CipherSuite; 3] = [
+    CipherSuite::XChaCha20Poly1305,
+    CipherSuite::ChaCha20Poly1305,
+    CipherSuite::Aes256Gcm,
+];
+
+// backend のシミュレータ（`cargo run -p backend --bin simulator`）が表示するptyのパスを指定する環境変数
+const SIMULATOR_PORT_ENV: &str = "ESP32_SIMULATOR_PORT";
+
 
 #[tauri::command]
 fn list_serial_ports() -> Result<Vec<String>, String> {
-    match serialport::available_ports() {
-        Ok(ports) => {
-            let port_names: Vec<String> = ports.into_iter()
-                .map(|p| p.port_name)
-                .collect();
-            Ok(port_names)
+    let mut ports = transport::available_ports()
+        .map_err(|e| format!("Failed to list serial ports: {}", e))?;
+    // ptyはシリアルポートとして列挙されないため、シミュレータのポートを先頭に追加する
+    if let Ok(port) = std::env::var(SIMULATOR_PORT_ENV) {
+        if !port.is_empty() && !ports.contains(&port) {
+            ports.insert(0, port);
         }
-        Err(e) => Err(format!("Failed to list serial ports: {}", e))
     }
+    Ok(ports)
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn start_serial_listener(
     app: tauri::AppHandle, 
     msg_state: State<'_, Arc<Mutex<MessageState>>>, 
     port_name_state: State<'_, Arc<Mutex<PortNameState>>>,
-    serial_port_state: State<'_, SharedSerialPort>,
+    link_state: State<'_, SharedLink>,
+    crypto_state: State<'_, SharedCryptoState>,
+    pending_state: State<'_, SharedPendingRequests>,
+    device_state: State<'_, SharedDeviceState>,
     port_name: String
 ) -> Result<(), String> {
     // 二重起動を防ぐ
     if START.set(()).is_err() {
         return Ok(());
     }
-    
-    let shared_msg_state = msg_state.inner().clone();
-    let shared_port_name_state = port_name_state.inner().clone();
-    let shared_serial_port = serial_port_state.inner().clone();
 
     // ポート名を保存
     {
-        let mut port_lock = shared_port_name_state.lock().unwrap();
+        let mut port_lock = port_name_state.lock().unwrap();
         port_lock.0 = port_name.clone();
     }
 
-    thread::spawn(move || {
-        let mut reconnect_delay = 1;
+    let listener = Listener {
+        app,
+        msg: msg_state.inner().clone(),
+        link: link_state.inner().clone(),
+        crypto: crypto_state.inner().clone(),
+        pending: pending_state.inner().clone(),
+        device: device_state.inner().clone(),
+    };
+    tauri::async_runtime::spawn(listener.run(port_name));
+
+    Ok(())
+}
+
+// シリアルポートの受信タスク（送信もこのタスクがチャネルで行う）
+struct Listener {
+    app: tauri::AppHandle,
+    msg: Arc<Mutex<MessageState>>,
+    link: SharedLink,
+    crypto: SharedCryptoState,
+    pending: SharedPendingRequests,
+    device: SharedDeviceState,
+}
+
+impl Listener {
+    // 切断されたら再接続して受信を続ける
+    async fn run(self, port_name: String) {
+        // 115200bps 8N1・DTR/RTSの設定と、1〜5秒の再接続待機は共通ライブラリが行う
+        let mut serial = ReconnectingSerial::new(SerialConfig::new(port_name.as_str()));
         
         loop {
-            match serialport::new(&port_name, 115_200)
-                .timeout(Duration::from_millis(50)) // 短いタイムアウト
-                .data_bits(serialport::DataBits::Eight)
-                .parity(serialport::Parity::None)
-                .stop_bits(serialport::StopBits::One)
-                .flow_control(serialport::FlowControl::None)
-                .open()
-            {
-                Ok(mut port) => {
+            match serial.connect_async().await {
+                Ok(port) => {
                     println!("✅ Successfully opened serial port: {}", &port_name);
-                    reconnect_delay = 1; // リセット
                     
-                    // DTRとRTSを適切に設定
-                    if let Err(e) = port.write_data_terminal_ready(true) {
-                        println!("⚠️ Failed to set DTR: {}", e);
-                    }
-                    if let Err(e) = port.write_request_to_send(false) {
-                        println!("⚠️ Failed to set RTS: {}", e);
-                    }
+                    // 送信口を共有状態に保存（コマンドはこのタスクが送信する）
+                    let (sender, outgoing) = mpsc::unbounded_channel();
+                    *self.link.lock().unwrap() = Some(sender);
                     
-                    // ESP32の起動を待つ
-                    std::thread::sleep(Duration::from_millis(100));
+                    self.serve(port, outgoing).await;
+                    self.disconnected();
                     
-                    // ポートを共有状態に保存（送信用）
-                    let port_for_writing = port.try_clone().unwrap();
-                    {
-                        let mut serial_lock = shared_serial_port.lock().unwrap();
-                        *serial_lock = Some(port_for_writing);
+                    println!("🔌 Serial connection lost, reconnecting in {}s...", serial.retry_delay().as_secs());
+                }
+                Err(e) => {
+                    println!("❌ Serial open failed: {} (retry in {}s)", e, serial.retry_delay().as_secs());
+                }
+            }
+            
+            serial.wait_async().await;
+        }
+    }
+    
+    // 1回の接続の間、受信したパケットと送信待ちのコマンドを処理する
+    async fn serve(&self, port: SerialStream, mut outgoing: mpsc::UnboundedReceiver<Outgoing>) {
+        let psk = self.crypto.lock().unwrap().psk.clone();
+        let mut channel = AsyncSecureChannel::new(port, psk);
+        
+        // 起動済みのESP32は起動通知を再送しないため、機能情報を問い合わせる
+        self.request_info(&mut channel, false).await;
+        
+        // セッション鍵を確立するためハンドシェイクを開始
+        let mut pending_handshake = None;
+        self.start_handshake(&mut channel, &mut pending_handshake).await;
+        
+        loop {
+            tokio::select! {
+                result = channel.recv_packet() => {
+                    self.forward_logs(&mut channel);
+                    match result {
+                        Ok(packet) => self.handle_packet(&mut channel, &mut pending_handshake, packet).await,
+                        // 非同期のポートにタイムアウトはなく、EOFは切断として再接続する
+                        Err(e) => {
+                            println!("📡 Read error: {}", e);
+                            break;
+                        }
                     }
-                    
-                    // 受信専用でポートを使用（バイト単位で読み取り）
-                    let mut buffer = [0u8; 1024];
-                    let mut line_buffer = String::new();
-                    
-                    loop {
-                        match port.read(&mut buffer) {
-                            Ok(0) => {
-                                // EOF時も接続は維持、少し待機
-                                std::thread::sleep(Duration::from_millis(10));
-                                continue;
-                            }
-                            Ok(bytes_read) => {
-                                // 受信データを文字列として処理
-                                if let Ok(received_str) = std::str::from_utf8(&buffer[..bytes_read]) {
-                                    line_buffer.push_str(received_str);
-                                    
-                                    // 改行を見つけたら行を処理
-                                    while let Some(newline_pos) = line_buffer.find('\n') {
-                                        let line = line_buffer[..newline_pos].trim();
-                                        if !line.is_empty() {
-                                            println!("📨 Received: {}", line);
-                                            
-                                            // まず平文JSONレスポンスをチェック
-                                            if let Ok(response) = serde_json::from_str::<Response>(line) {
-                                                // 平文JSONレスポンス
-                                                println!("📨 Plain JSON response received: status={}, message={}", response.status, response.message);
-                                                app.emit("response-received", &response).ok();
-                                                if let Ok(mut lock) = shared_msg_state.lock() {
-                                                    lock.0 = format!("✅ {}", response.message);
-                                                }
-                                            } else if let Ok(encrypted) = serde_json::from_str::<EncryptedMessage>(line) {
-                                                // 暗号化メッセージの場合、即座に復号化を試行
-                                                println!("🔐 Encrypted message received, attempting decryption...");
-                                                app.emit("encrypted-message-received", &encrypted).ok();
-                                                
-                                                // 復号化を試行
-                                                match decrypt_received_message_internal(&encrypted) {
-                                                    Ok(decrypted_text) => {
-                                                        println!("✅ Decrypted: {}", decrypted_text);
-                                                        
-                                                        // 復号化されたテキストがJSONかチェック
-                                                        if let Ok(response) = serde_json::from_str::<Response>(&decrypted_text) {
-                                                            // JSONレスポンスの場合はメッセージ部分を表示
-                                                            app.emit("response-received", &response).ok();
-                                                            if let Ok(mut lock) = shared_msg_state.lock() {
-                                                                lock.0 = format!("🔓 {}", response.message);
-                                                            }
-                                                        } else {
-                                                            // 通常のテキストの場合
-                                                            if let Ok(mut lock) = shared_msg_state.lock() {
-                                                                lock.0 = format!("🔓 {}", decrypted_text);
-                                                            }
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        println!("❌ Decryption failed: {}", e);
-                                                        if let Ok(mut lock) = shared_msg_state.lock() {
-                                                            lock.0 = format!("❌ Decryption error: {}", e);
-                                                        }
-                                                    }
-                                                }
-                                            } else {
-                                                // その他のメッセージ
-                                                println!("📨 Raw message received: {}", line);
-                                                app.emit("raw-message", line).ok();
-                                                if let Ok(mut lock) = shared_msg_state.lock() {
-                                                    lock.0 = line.to_string();
-                                                }
-                                            }
-                                        }
-                                        
-                                        // 処理済みの行をバッファから削除
-                                        line_buffer.drain(..=newline_pos);
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                // タイムアウトエラーは正常動作として扱う
-                                if e.kind() == std::io::ErrorKind::TimedOut {
-                                    // タイムアウトは正常、接続を維持
-                                    continue;
-                                } else {
-                                    println!("📡 Read error (non-timeout): {}", e);
-                                    break;
-                                }
-                            }
+                }
+                Some(request) = outgoing.recv() => {
+                    let result = self.send(&mut channel, request.command, request.encrypted).await;
+                    request.sent.send(result).ok();
+                }
+            }
+        }
+    }
+    
+    // フレーム外のデータはESP32のログ出力として扱う
+    fn forward_logs(&self, channel: &mut AsyncSecureChannel<SerialStream>) {
+        for bytes in channel.take_discarded() {
+            for log_line in String::from_utf8_lossy(&bytes).lines() {
+                let log_line = log_line.trim();
+                if !log_line.is_empty() {
+                    println!("📟 ESP32 log: {}", log_line);
+                    self.app.emit("device-log", log_line).ok();
+                }
+            }
+        }
+    }
+    
+    async fn handle_packet(
+        &self,
+        channel: &mut AsyncSecureChannel<SerialStream>,
+        pending_handshake: &mut Option<Handshake>,
+        packet: Packet,
+    ) {
+        match packet {
+            Packet::Response(response) => {
+                // 平文JSONレスポンス
+                println!("📨 Plain JSON response received: status={}, message={}", response.status, response.message);
+                self.handle_response(channel, pending_handshake, false, &response).await;
+            }
+            Packet::Handshake(reply) => {
+                // ハンドシェイク応答（やり直す前のHelloへの応答は読み飛ばす）
+                if pending_handshake.as_ref().is_some_and(|h| !h.is_reply_to(&reply)) {
+                    println!("⚠️ Handshake reply for a previous hello ignored");
+                    return;
+                }
+                match pending_handshake.take().map(|h| h.finish(&reply)) {
+                    Some(Ok(session)) => {
+                        let suite = session.cipher_suite();
+                        println!("🤝 Handshake completed, session key established ({:?})", suite);
+                        channel.set_crypto(session.clone());
+                        if let Ok(mut crypto) = self.crypto.lock() {
+                            crypto.crypto_system = session;
+                            crypto.is_ready = true;
                         }
+                        self.app.emit("handshake-completed", suite).ok();
+                        
+                        // 平文の機能情報は改ざんされていても検出できないため、暗号化して問い合わせ直す
+                        self.request_info(channel, true).await;
                     }
-                    
-                    // ポートをクリア
-                    {
-                        let mut serial_lock = shared_serial_port.lock().unwrap();
-                        *serial_lock = None;
+                    Some(Err(e)) => {
+                        println!("❌ Handshake failed: {}", e);
+                        self.app.emit("handshake-failed", e.to_string()).ok();
+                    }
+                    None => {
+                        println!("⚠️ Unexpected handshake message ignored");
                     }
-                    
-                    println!("🔌 Serial connection lost, reconnecting in {}s...", reconnect_delay);
                 }
-                Err(e) => {
-                    println!("❌ Serial open failed: {} (retry in {}s)", e, reconnect_delay);
+            }
+            Packet::Encrypted(encrypted) => {
+                // 暗号化メッセージの場合、即座に復号化を試行
+                println!("🔐 Encrypted message received, attempting decryption...");
+                self.app.emit("encrypted-message-received", &encrypted).ok();
+                
+                match channel.open::<Response>(&encrypted) {
+                    Ok(response) => {
+                        println!("✅ Decrypted: status={}, message={}", response.status, response.message);
+                        self.handle_response(channel, pending_handshake, true, &response).await;
+                    }
+                    Err(e) => {
+                        println!("❌ Decryption failed: {}", e);
+                        if let Ok(mut lock) = self.msg.lock() {
+                            lock.0 = format!("❌ Decryption error: {}", e);
+                        }
+                    }
                 }
             }
-            
-            thread::sleep(Duration::from_secs(reconnect_delay));
-            reconnect_delay = std::cmp::min(reconnect_delay + 1, 5); // 最大5秒
+            Packet::Command(command) => {
+                println!("⚠️ Unexpected command from device ignored: {}", command.action);
+            }
         }
-    });
-
-    Ok(())
-}
-
-// ESP32にコマンドを送信する関数
-#[tauri::command]
-fn send_command(
-    serial_port_state: State<'_, SharedSerialPort>,
-    action: String, 
-    data: Option<String>
-) -> Result<String, String> {
-    let command = Command { action: action.clone(), data };
-    let json_command = serde_json::to_string(&command)
-        .map_err(|e| format!("JSON serialization error: {}", e))?;
+    }
     
-    let mut serial_lock = serial_port_state.lock().unwrap();
-    if let Some(port) = serial_lock.as_mut() {
-        let command_with_newline = json_command.clone() + "\n";
-        match port.write_all(command_with_newline.as_bytes()) {
-            Ok(_) => {
-                // フラッシュして即座に送信
-                if let Err(e) = port.flush() {
-                    println!("⚠️ Flush warning: {}", e);
+    // 受信したレスポンスを応答待ちのリクエストと対応付けてフロントエンドに通知
+    async fn handle_response(
+        &self,
+        channel: &mut AsyncSecureChannel<SerialStream>,
+        pending_handshake: &mut Option<Handshake>,
+        encrypted: bool,
+        response: &Response,
+    ) {
+        let has_session = self.crypto.lock().unwrap().is_ready;
+        complete_pending_request(&self.pending, response);
+        // セッション確立後は、暗号化された応答の機能情報だけを受け入れる
+        if encrypted || !has_session {
+            update_device_info(&self.app, &self.device, response);
+        } else if is_device_info(response) {
+            println!("⚠️ Plaintext device info ignored during a secure session");
+        }
+        self.app.emit("response-received", response).ok();
+        if let Ok(mut lock) = self.msg.lock() {
+            lock.0 = describe_response(if encrypted { "🔓" } else { "✅" }, response);
+        }
+        
+        // 平文の起動通知・エラーは認証されないため、それだけでは確立済みのセッションを破棄しない
+        if is_ready_event(response) {
+            if has_session {
+                // 再起動していればセッション鍵で暗号化したコマンドを復号できず、エラーが返る
+                println!("🔎 Ready event received during a session, verifying the session key");
+                let probe = Command::from(Request::Ping);
+                if let Err(e) = self.send(channel, probe, true).await {
+                    println!("⚠️ Failed to verify the session key: {}", e);
                 }
-                println!("📤 Sent command: {}", json_command);
-                
-                // ESP32の処理時間を確保するため少し待機
-                std::thread::sleep(std::time::Duration::from_millis(50));
-                
-                Ok(format!("Command '{}' sent successfully", action))
+            } else {
+                // 起動直後のESP32は以前のHelloを受け取っていない可能性がある
+                self.restart_session(channel, pending_handshake).await;
+            }
+        } else if has_session && is_session_rejected(response) {
+            // 再起動したESP32はセッション鍵を破棄しているため、ハンドシェイクをやり直す
+            self.restart_session(channel, pending_handshake).await;
+        }
+    }
+    
+    // ハンドシェイクのHelloを送信し、応答待ちにする
+    async fn start_handshake(&self, channel: &mut AsyncSecureChannel<SerialStream>, pending_handshake: &mut Option<Handshake>) {
+        let psk = self.crypto.lock().unwrap().psk.clone();
+        let (handshake, hello) = Handshake::initiate(&psk, &OFFERED_CIPHER_SUITES, &WireCodec::ALL);
+        *pending_handshake = Some(handshake);
+        match channel.send_packet(&Packet::Handshake(hello)).await {
+            Ok(_) => println!("🤝 Handshake hello sent"),
+            Err(e) => println!("⚠️ Failed to send handshake hello: {}", e),
+        }
+    }
+    
+    // セッションを破棄してPSKに戻し、ハンドシェイクをやり直す
+    async fn restart_session(&self, channel: &mut AsyncSecureChannel<SerialStream>, pending_handshake: &mut Option<Handshake>) {
+        let psk = {
+            let mut crypto = self.crypto.lock().unwrap();
+            crypto.is_ready = false;
+            crypto.psk.clone()
+        };
+        channel.set_crypto(psk);
+        // 再起動前のリクエストへの応答は届かない
+        self.pending.lock().unwrap().pending.clear();
+        println!("🔄 Renegotiating the session key");
+        self.app.emit("session-reset", ()).ok();
+        
+        self.start_handshake(channel, pending_handshake).await;
+    }
+    
+    // 機能情報を問い合わせる
+    async fn request_info(&self, channel: &mut AsyncSecureChannel<SerialStream>, encrypted: bool) {
+        let command = Command::from(Request::Info);
+        if let Err(e) = self.send(channel, command, encrypted).await {
+            println!("⚠️ Failed to request device info: {}", e);
+        }
+    }
+    
+    // リクエストIDを付けてコマンドを送信（暗号化はセッション確立後のみ）
+    async fn send(
+        &self,
+        channel: &mut AsyncSecureChannel<SerialStream>,
+        mut command: Command,
+        encrypted: bool,
+    ) -> Result<u32, String> {
+        if encrypted && !self.crypto.lock().unwrap().is_ready {
+            return Err("Session key not established. Please wait for the handshake to complete.".to_string());
+        }
+        
+        let id = self.pending.lock().unwrap().register(&command.action);
+        command.id = Some(id);
+        
+        // 暗号化・フレーム化・送信はチャネルが行う
+        let result = if encrypted {
+            channel.send(&command).await
+        } else {
+            channel.send_packet(&Packet::Command(command.clone())).await
+        };
+        
+        match result {
+            Ok(_) => {
+                println!("📤 Sent command #{}: {}{}", id, command.action, if encrypted { " (encrypted)" } else { "" });
+                Ok(id)
             }
             Err(e) => {
                 println!("❌ Write error: {}", e);
+                self.pending.lock().unwrap().complete(id);
                 Err(format!("Failed to send command: {}", e))
             }
         }
-    } else {
-        Err("Serial port not connected. Please start serial listener first.".to_string())
+    }
+    
+    // 送信口とセッションをクリア
+    fn disconnected(&self) {
+        *self.link.lock().unwrap() = None;
+        if let Ok(mut crypto) = self.crypto.lock() {
+            crypto.is_ready = false;
+        }
+        // 切断前のリクエストへの応答は届かない
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.pending.clear();
+        }
+        if let Ok(mut device) = self.device.lock() {
+            *device = DeviceState::default();
+        }
+    }
+}
+
+// レスポンスを表示用の文字列にする（エラーはエラーコード付き）
+fn describe_response(icon: &str, response: &Response) -> String {
+    match response.code {
+        Some(code) if response.is_error() => format!("❌ {} ({})", response.message, code),
+        _ => format!("{} {}", icon, response.message),
+    }
+}
+
+// 起動通知（ファームウェアが起動・再起動したときに送るイベント）か
+fn is_ready_event(response: &Response) -> bool {
+    response.status == ResponseStatus::Event && response.response_to.is_none()
+}
+
+// ESP32がセッション鍵で暗号化したコマンドを復号できなかったことを示すエラーか
+fn is_session_rejected(response: &Response) -> bool {
+    response.is_error()
+        && response.response_to.is_none()
+        && matches!(response.code, Some(ErrorCode::DecryptionFailed | ErrorCode::EncryptionRequired))
+}
+
+// 機能情報を含むレスポンス（info の応答・起動通知）か
+fn is_device_info(response: &Response) -> bool {
+    response.response_to.as_deref() == Some(Request::Info.action()) || is_ready_event(response)
+}
+
+// 機能情報を含むレスポンスから互換性を判定してイベントで通知
+//
+// ハンドシェイク前の平文の機能情報は仮のもので、セッション確立後に暗号化した info の応答で置き換える
+fn update_device_info(app: &tauri::AppHandle, device_state: &SharedDeviceState, response: &Response) {
+    if !is_device_info(response) {
+        return;
+    }
+    let is_info_response = response.response_to.as_deref() == Some(Request::Info.action());
+    
+    let info = response.payload.clone().and_then(|payload| serde_json::from_value::<DeviceInfo>(payload).ok());
+    let state = match info {
+        Some(info) => {
+            let compatibility = info.check_compatibility(&OFFERED_CIPHER_SUITES);
+            println!("ℹ️ Firmware v{} ({}), protocol v{}: {:?}", info.firmware_version, info.build_hash, info.protocol_version, compatibility);
+            DeviceState { info: Some(info), compatibility: Some(compatibility) }
+        }
+        // 平文を拒否するファームウェアには、ハンドシェイク後に暗号化して問い合わせ直す
+        None if response.code == Some(ErrorCode::EncryptionRequired) => {
+            println!("🔒 Firmware requires encryption, device info will be requested after the handshake");
+            return;
+        }
+        // info を知らない旧ファームウェアは Unknown command を返す
+        None if is_info_response && response.is_error() => {
+            println!("⚠️ Firmware does not report its capabilities (legacy firmware)");
+            DeviceState::default()
+        }
+        None => return,
+    };
+    
+    *device_state.lock().unwrap() = state.clone();
+    app.emit("device-info", &state).ok();
+    if state.compatibility != Some(Compatibility::Compatible) {
+        app.emit("device-mismatch", &state).ok();
     }
 }
 
+// 受信したレスポンスを応答待ちのリクエストと対応付ける
+fn complete_pending_request(pending: &SharedPendingRequests, response: &Response) {
+    let Some(id) = response.id else {
+        return;
+    };
+    match pending.lock().unwrap().complete(id) {
+        Some((action, elapsed)) => {
+            println!("⏱️ Request #{} ({}) answered in {} ms", id, action, elapsed.as_millis());
+        }
+        None => {
+            println!("⚠️ Response for unknown request #{} ignored", id);
+        }
+    }
+}
+
+// 受信タスクにコマンドの送信を依頼し、付与されたリクエストIDを返す
+async fn send_command_internal(
+    link: &SharedLink,
+    device_state: &SharedDeviceState,
+    command: Command,
+    encrypted: bool
+) -> Result<u32, String> {
+    // 互換性のないファームウェアや未対応のコマンドは送信しない
+    device_state.lock().unwrap().check_command(&command.action, encrypted)?;
+    
+    let not_connected = || "Serial port not connected. Please start serial listener first.".to_string();
+    let sender = link.lock().unwrap().clone().ok_or_else(not_connected)?;
+    let (sent, result) = oneshot::channel();
+    sender.send(Outgoing { command, encrypted, sent }).map_err(|_| not_connected())?;
+    // 送信前に切断されると結果の通知先は破棄される
+    result.await.map_err(|_| not_connected())?
+}
+
+// ESP32にコマンドを送信する関数（平文）
+//
+// `params` は構造化されたパラメータ、`binary` はバイト列（フロントエンドからは数値の配列）
+#[tauri::command]
+async fn send_command(
+    link_state: State<'_, SharedLink>,
+    device_state: State<'_, SharedDeviceState>,
+    action: String, 
+    data: Option<String>,
+    params: Option<serde_json::Value>,
+    binary: Option<Vec<u8>>
+) -> Result<String, String> {
+    let command = Command { data, params, binary, ..Command::new(action.clone()) };
+    send_command_internal(&link_state, &device_state, command, false).await
+        .map(|id| format!("Command '{}' sent successfully (id={})", action, id))
+}
+
+// 型付きリクエストを送信する関数（暗号化はセッション確立後のみ）
+//
+// 戻り値は応答の `id` と対応するリクエストID
+#[tauri::command]
+async fn send_request(
+    link_state: State<'_, SharedLink>,
+    device_state: State<'_, SharedDeviceState>,
+    request: Request,
+    encrypted: bool
+) -> Result<u32, String> {
+    send_command_internal(&link_state, &device_state, Command::from(&request), encrypted).await
+}
+
 #[tauri::command]
 fn get_message(state: State<'_, Arc<Mutex<MessageState>>>) -> Option<String> {
     state.lock().ok().map(|m| m.0.clone())
@@ -250,120 +582,195 @@ fn get_message(state: State<'_, Arc<Mutex<MessageState>>>) -> Option<String> {
 // 軽量暗号化システム初期化
 #[tauri::command]
 fn initialize_lightweight_crypto(
-    crypto_state: State<'_, Arc<Mutex<SimpleCryptoState>>>
+    crypto_state: State<'_, SharedCryptoState>
 ) -> Result<String, String> {
-    {
+    let psk = load_psk()?;
+    let is_ready = {
         let mut crypto = crypto_state.lock().unwrap();
-        crypto.crypto_system = create_default_crypto();
-        crypto.is_ready = true;
-    }
+        crypto.psk = psk;
+        crypto.is_ready
+    };
     
     println!("🔐 Lightweight crypto system initialized");
-    Ok("Lightweight crypto system ready".to_string())
+    if is_ready {
+        Ok("Lightweight crypto system ready".to_string())
+    } else {
+        Ok("Lightweight crypto system waiting for handshake".to_string())
+    }
 }
 
 // 双方向通信テスト用コマンド
 #[tauri::command]
-fn test_bidirectional_communication(
-    serial_port_state: State<'_, SharedSerialPort>
+async fn test_bidirectional_communication(
+    link_state: State<'_, SharedLink>,
+    device_state: State<'_, SharedDeviceState>
 ) -> Result<String, String> {
-    send_command(serial_port_state, "test_bidirectional".to_string(), Some("GUI bidirectional test".to_string()))
-}
-
-// 内部復号化関数（static crypto使用）
-fn decrypt_received_message_internal(encrypted: &EncryptedMessage) -> Result<String, String> {
-    let crypto_system = create_default_crypto();
-    crypto_system.decrypt(encrypted)
-        .map_err(|e| e.to_string())
+    let request = Request::Echo { text: "GUI bidirectional test".to_string() };
+    send_command_internal(&link_state, &device_state, Command::from(&request), false).await
+        .map(|id| format!("Bidirectional test request sent (id={})", id))
 }
 
 // 受信した暗号化メッセージを復号化
 #[tauri::command]
 fn decrypt_received_message(
-    crypto_state: State<'_, Arc<Mutex<SimpleCryptoState>>>,
+    crypto_state: State<'_, SharedCryptoState>,
     encrypted: EncryptedMessage
 ) -> Result<String, String> {
     let crypto_system = {
         let crypto = crypto_state.lock().unwrap();
         if !crypto.is_ready {
-            return Err("Crypto not initialized. Please initialize first.".to_string());
+            return Err("Session key not established. Please wait for the handshake to complete.".to_string());
         }
         crypto.crypto_system.clone()
     };
     
-    crypto_system.decrypt(&encrypted)
+    crypto_system.decrypt_to_response(&encrypted)
+        .map(|response| response.message)
         .map_err(|e| e.to_string())
 }
 
 // 軽量暗号化コマンド送信
 #[tauri::command]
-fn send_lightweight_encrypted_command(
-    serial_port_state: State<'_, SharedSerialPort>,
-    crypto_state: State<'_, Arc<Mutex<SimpleCryptoState>>>,
+async fn send_lightweight_encrypted_command(
+    link_state: State<'_, SharedLink>,
+    device_state: State<'_, SharedDeviceState>,
     action: String,
-    data: Option<String>
+    data: Option<String>,
+    params: Option<serde_json::Value>,
+    binary: Option<Vec<u8>>
 ) -> Result<String, String> {
-    // 暗号化システムを取得
-    let crypto_system = {
-        let crypto = crypto_state.lock().unwrap();
-        if !crypto.is_ready {
-            return Err("Lightweight crypto not initialized. Please initialize first.".to_string());
-        }
-        crypto.crypto_system.clone()
-    };
-    
-    // コマンドを作成
-    let command = Command { action: action.clone(), data };
-    
-    // 暗号化
-    let encrypted = crypto_system.encrypt_command(&command)
-        .map_err(|e| e.to_string())?;
-    let encrypted_json = serde_json::to_string(&encrypted)
-        .map_err(|e| format!("Encrypted message serialization error: {}", e))?;
-    
-    let mut serial_lock = serial_port_state.lock().unwrap();
-    if let Some(port) = serial_lock.as_mut() {
-        let message_with_newline = encrypted_json + "\n";
-        match port.write_all(message_with_newline.as_bytes()) {
-            Ok(_) => {
-                if let Err(e) = port.flush() {
-                    println!("⚠️ Flush warning: {}", e);
-                }
-                println!("🔐 Sent lightweight encrypted command: {}", action);
-                
-                // ESP32の処理時間を確保するため少し待機
-                std::thread::sleep(std::time::Duration::from_millis(50));
-                
-                Ok(format!("Lightweight encrypted command '{}' sent successfully", action))
-            }
-            Err(e) => {
-                Err(format!("Failed to send encrypted command: {}", e))
-            }
+    let command = Command { data, params, binary, ..Command::new(action.clone()) };
+    send_command_internal(&link_state, &device_state, command, true).await
+        .map(|id| format!("Lightweight encrypted command '{}' sent successfully (id={})", action, id))
+}
+
+// 環境変数でプロビジョニングした事前共有鍵（未設定ならデモ用の固定鍵）
+fn load_psk() -> Result<CryptoSystem, String> {
+    match (std::env::var(PASSPHRASE_ENV), std::env::var(KDF_PARAMS_ENV)) {
+        (Ok(passphrase), Ok(params)) => create_provisioned_crypto(&passphrase, &params, Role::Host)
+            .map_err(|e| format!("{} is not valid KdfParams JSON: {}", KDF_PARAMS_ENV, e)),
+        _ => {
+            println!("⚠️ {} / {} not set, using the demo key", PASSPHRASE_ENV, KDF_PARAMS_ENV);
+            Ok(create_default_crypto(Role::Host))
         }
-    } else {
-        Err("Serial port not connected. Please start serial listener first.".to_string())
     }
 }
 
-fn main() {
-    tauri::Builder::default()
-        .manage(Arc::new(Mutex::new(MessageState(String::new()))))
-        .manage(Arc::new(Mutex::new(PortNameState(String::new()))))
-        .manage(Arc::new(Mutex::new(SimpleCryptoState {
-            crypto_system: create_default_crypto(),
-            is_ready: true,
-        })))
-        .manage(Arc::new(Mutex::<Option<Box<dyn serialport::SerialPort>>>::new(None)) as SharedSerialPort)
-        .invoke_handler(tauri::generate_handler![
+// Tauriコマンドの一覧を受け取るマクロに渡す
+//
+// `generate_handler!` への登録と、フロントエンドのコマンド名の型（bindings/CommandName.ts）の生成で共有する
+macro_rules! with_commands {
+    ($($callback:ident)::+) => {
+        $($callback)::+![
             list_serial_ports,
             start_serial_listener,
             send_command,
+            send_request,
             get_message,
             initialize_lightweight_crypto,
             decrypt_received_message,
             send_lightweight_encrypted_command,
             test_bidirectional_communication
-        ])
+        ]
+    };
+}
+
+fn main() {
+    let psk = load_psk().expect("Failed to load the pre-shared key");
+    let crypto_state: SharedCryptoState = Arc::new(Mutex::new(SimpleCryptoState {
+        crypto_system: psk.clone(),
+        psk,
+        is_ready: false,
+    }));
+
+    tauri::Builder::default()
+        .manage(Arc::new(Mutex::new(MessageState(String::new()))))
+        .manage(Arc::new(Mutex::new(PortNameState(String::new()))))
+        .manage(crypto_state)
+        .manage(Arc::new(Mutex::new(PendingRequests::default())) as SharedPendingRequests)
+        .manage(Arc::new(Mutex::new(DeviceState::default())) as SharedDeviceState)
+        .manage(Arc::new(Mutex::new(None)) as SharedLink)
+        .invoke_handler(with_commands!(tauri::generate_handler))
         .run(tauri::generate_context!())
         .expect("error while running tauri");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod bindings {
+    use super::*;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::{Path, PathBuf};
+    use esp32_tauri_crypto::DeviceStatus;
+    use ts_rs::TS;
+
+    const BINDINGS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../src/bindings");
+
+    // コマンド名の一覧
+    macro_rules! command_names {
+        ($($command:ident),* $(,)?) => { &[$(stringify!($command)),*] };
+    }
+
+    // `generate_handler!` に登録したコマンド名
+    const COMMAND_NAMES: &[&str] = with_commands!(command_names);
+
+    // api.ts が `invoke` に渡せるコマンド名の型
+    fn export_command_names(out_dir: &Path) {
+        let names: Vec<String> = COMMAND_NAMES.iter().map(|name| format!("\"{}\"", name)).collect();
+        let content = format!(
+            "// This file was generated from `with_commands!` in src-tauri/src/main.rs. Do not edit this file manually.\n\nexport type CommandName = {};\n",
+            names.join(" | ")
+        );
+        std::fs::create_dir_all(out_dir).unwrap();
+        std::fs::write(out_dir.join("CommandName.ts"), content).unwrap();
+    }
+
+    // フロントエンドの型定義を出力
+    fn export_to(out_dir: &Path) {
+        Command::export_all_to(out_dir).unwrap();
+        Request::export_all_to(out_dir).unwrap();
+        Response::export_all_to(out_dir).unwrap();
+        EncryptedMessage::export_all_to(out_dir).unwrap();
+        DeviceState::export_all_to(out_dir).unwrap();
+        DeviceStatus::export_all_to(out_dir).unwrap();
+        export_command_names(out_dir);
+    }
+
+    // ディレクトリ以下のファイル（相対パス → 内容）
+    fn read_tree(dir: &Path) -> BTreeMap<PathBuf, String> {
+        let mut files = BTreeMap::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(current) = dirs.pop() {
+            for entry in std::fs::read_dir(&current).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let content = std::fs::read_to_string(&path).unwrap();
+                    files.insert(path.strip_prefix(dir).unwrap().to_path_buf(), content);
+                }
+            }
+        }
+        files
+    }
+
+    // gui/src/bindings が生成結果と一致するか（`UPDATE_BINDINGS=1 cargo test` で再生成）
+    #[test]
+    fn bindings_are_up_to_date() {
+        if std::env::var_os("UPDATE_BINDINGS").is_some() {
+            std::fs::remove_dir_all(BINDINGS_DIR).ok();
+            export_to(Path::new(BINDINGS_DIR));
+            return;
+        }
+
+        let out_dir = std::env::temp_dir().join(format!("gui-bindings-{}", std::process::id()));
+        export_to(&out_dir);
+        let generated = read_tree(&out_dir);
+        std::fs::remove_dir_all(&out_dir).ok();
+
+        let current = read_tree(Path::new(BINDINGS_DIR));
+        let stale: BTreeSet<_> = generated.keys().chain(current.keys())
+            .filter(|path| generated.get(*path) != current.get(*path))
+            .collect();
+        assert!(stale.is_empty(), "gui/src/bindings is out of date ({:?}). Run `UPDATE_BINDINGS=1 cargo test` in gui/src-tauri.", stale);
+    }
+}
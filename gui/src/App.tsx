@@ -1,65 +1,134 @@
-import { useState, useEffect } from "react";
-import { invoke } from "@tauri-apps/api/core";
-import { listen } from "@tauri-apps/api/event";
+import { useState, useEffect, useRef } from "react";
+import * as api from "./api";
+import type { CommandInfo } from "./bindings/CommandInfo";
+import type { DeviceState } from "./bindings/DeviceState";
+import type { DeviceStatus } from "./bindings/DeviceStatus";
+import type { ResetReason } from "./bindings/ResetReason";
+import type { Request } from "./bindings/Request";
 import "./App.css";
 
-// ESP32レスポンス型定義
-interface ESP32Response {
-  status: string;
-  message: string;
-  timestamp: number;
-  response_to?: string;
-}
+// 互換性の問題を表示用の文字列にする
+const describeMismatch = (device: DeviceState): string | null => {
+  const { info, compatibility } = device;
+  if (!info || !compatibility) {
+    return "⚠️ ファームウェアが機能情報を報告しません（旧バージョン）。一部の機能が動作しない可能性があります";
+  }
+  switch (compatibility.level) {
+    case "compatible":
+      return null;
+    case "degraded": {
+      const problems: string[] = [];
+      if (compatibility.missing_commands.length > 0) {
+        problems.push(`未対応のコマンド: ${compatibility.missing_commands.join(", ")}`);
+      }
+      if (!compatibility.encryption_available) {
+        problems.push("共通の暗号スイートがないため暗号化通信は使えません");
+      }
+      return `⚠️ ファームウェア v${info.firmware_version} (${info.build_hash}) は一部の機能に対応していません。${problems.join("、")}`;
+    }
+    case "incompatible":
+      return `❌ ファームウェア v${info.firmware_version} (${info.build_hash}) はプロトコル v${compatibility.device_protocol} です（このアプリは v${compatibility.host_protocol}）。ファームウェアを更新してください`;
+  }
+};
 
-// 暗号化メッセージ型定義
-interface EncryptedMessage {
-  ciphertext: string;
-  nonce: string;
-}
+// パニック・ウォッチドッグ・電源異常・CPUロックアップによる再起動は現地での不具合を疑う
+const ABNORMAL_RESET_REASONS: ResetReason[] = [
+  "panic",
+  "interrupt_watchdog",
+  "task_watchdog",
+  "watchdog",
+  "brownout",
+  "efuse",
+  "power_glitch",
+  "cpu_lockup",
+];
 
+// 稼働時間を表示用の文字列にする
+const formatUptime = (uptimeMs: number): string => {
+  const seconds = Math.floor(uptimeMs / 1000);
+  const h = Math.floor(seconds / 3600);
+  const m = Math.floor((seconds % 3600) / 60);
+  return `${h}h ${m}m ${seconds % 60}s`;
+};
 
 function App() {
   const [message, setMessage] = useState<string>("");
   const [serialPorts, setSerialPorts] = useState<string[]>([]);
   const [selectedPort, setSelectedPort] = useState<string>("");
   const [isListening, setIsListening] = useState<boolean>(false);
+  const [device, setDevice] = useState<DeviceState | null>(null);
+  const [deviceStatus, setDeviceStatus] = useState<DeviceStatus | null>(null);
+  // 応答待ちのリクエスト（リクエストID → アクション名）
+  const pendingRequests = useRef<Map<number, string>>(new Map());
 
   useEffect(() => {
     // JSON レスポンス受信リスナー
-    const responseListener = listen<ESP32Response>("response-received", (event) => {
+    const responseListener = api.listenTo("response-received", (event) => {
       const response = event.payload;
-      setMessage(response.message);
+      if (response.id !== null) {
+        const action = pendingRequests.current.get(response.id);
+        pendingRequests.current.delete(response.id);
+        console.log(`📨 Response to #${response.id} (${action ?? "unknown"}): ${response.status}`);
+      }
+      if (response.response_to === "status" && response.status === "ok" && response.payload) {
+        setDeviceStatus(response.payload as unknown as DeviceStatus);
+      }
+      if (response.status === "error") {
+        setMessage(`❌ ${response.message} (${response.code ?? "unknown"})`);
+      } else {
+        setMessage(response.message);
+      }
     });
 
-    // 生メッセージ受信リスナー
-    const rawListener = listen<string>("raw-message", (event) => {
-      setMessage(event.payload);
+    // 暗号化メッセージ受信リスナー
+    // 復号化はバックエンドで1回だけ行われ、結果は response-received で届く
+    // （同じメッセージを再度復号化するとリプレイとして拒否される）
+    const encryptedListener = api.listenTo("encrypted-message-received", (event) => {
+      console.log(`🔐 Encrypted message received (seq=${event.payload.seq})`);
     });
 
-    // 暗号化メッセージ受信リスナー
-    const encryptedListener = listen<EncryptedMessage>("encrypted-message-received", (event) => {
-      // 復号化を試行
-      decryptReceivedMessage(event.payload);
+    // フレーム外で受信したESP32のログ出力（起動バナーなど）
+    const deviceLogListener = api.listenTo("device-log", (event) => {
+      console.log(`📟 ${event.payload}`);
+    });
+
+
+    // 接続したファームウェアの機能情報（互換性に問題があれば device-mismatch も届く）
+    const deviceInfoListener = api.listenTo("device-info", (event) => {
+      setDevice(event.payload);
     });
 
+    const deviceMismatchListener = api.listenTo("device-mismatch", (event) => {
+      console.warn(describeMismatch(event.payload));
+    });
+
+    // 再起動前のリクエストへの応答は届かず、セッションは確立し直される
+    const sessionResetListener = api.listenTo("session-reset", () => {
+      pendingRequests.current.clear();
+      setDeviceStatus(null);
+    });
 
     // Load available serial ports on startup
     loadSerialPorts();
 
     return () => {
       responseListener.then(f => f());
-      rawListener.then(f => f());
       encryptedListener.then(f => f());
+      deviceLogListener.then(f => f());
+      deviceInfoListener.then(f => f());
+      deviceMismatchListener.then(f => f());
+      sessionResetListener.then(f => f());
     };
   }, []);
 
   const loadSerialPorts = async () => {
     try {
-      const ports = await invoke<string[]>("list_serial_ports");
+      const ports = await api.listSerialPorts();
       setSerialPorts(ports);
       if (ports.length > 0 && !selectedPort) {
-        // Auto-select ESP32-like ports
+        // Auto-select ESP32-like ports (including the simulator's pty)
         const esp32Port = ports.find(p => 
+          p.includes("/dev/pts/") || 
           p.includes("usbserial") || 
           p.includes("ttyUSB") || 
           p.includes("ttyACM")
@@ -73,7 +142,7 @@ function App() {
 
   const getLatestMessage = async () => {
     try {
-      const latestMessage = await invoke<string | null>("get_message");
+      const latestMessage = await api.getMessage();
       if (latestMessage) {
         setMessage(latestMessage);
       }
@@ -89,7 +158,7 @@ function App() {
     }
     
     try {
-      await invoke("start_serial_listener", { portName: selectedPort });
+      await api.startSerialListener(selectedPort);
       setIsListening(true);
       console.log(`Serial listener started on ${selectedPort}`);
     } catch (error) {
@@ -98,27 +167,25 @@ function App() {
     }
   };
 
-  // 暗号化メッセージを復号化
-  const decryptReceivedMessage = async (encryptedMsg: EncryptedMessage) => {
+  // ESP32にリクエストを送信する関数
+  const sendRequest = async (request: Request, encrypted = false) => {
     try {
-      const decrypted = await invoke<string>("decrypt_received_message", { 
-        encrypted: encryptedMsg
-      });
-      setMessage(`🔓 ${decrypted}`);
+      const id = await api.sendRequest(request, encrypted);
+      pendingRequests.current.set(id, request.action);
+      console.log(`📤 Request #${id} sent: ${request.action}`);
     } catch (error) {
-      console.error("復号化に失敗:", error);
-      setMessage(`❌ 復号化エラー: ${error}`);
+      console.error("Failed to send command:", error);
+      alert(`コマンド送信に失敗: ${error}`);
     }
   };
 
-  // ESP32にコマンドを送信する関数
-  const sendCommand = async (action: string, data?: string) => {
+  // ファームウェアが報告したコマンドを送信（平文で実行できないコマンドは暗号化して送る）
+  const sendDiscoveredCommand = async (command: CommandInfo) => {
     try {
-      const result = await invoke<string>("send_command", { 
-        action,
-        data: data || null
-      });
-      console.log(result);
+      const result = command.privilege === "public"
+        ? await api.sendCommand(command.name)
+        : await api.sendLightweightEncryptedCommand(command.name);
+      console.log(`📤 ${result}`);
     } catch (error) {
       console.error("Failed to send command:", error);
       alert(`コマンド送信に失敗: ${error}`);
@@ -171,9 +238,90 @@ function App() {
         </div>
         <div style={{ fontSize: "14px", color: "#666" }}>
           Status: {isListening ? "🟢 接続中" : "🔴 停止中"}
+          {device?.info && ` ・ Firmware v${device.info.firmware_version} (${device.info.build_hash})`}
         </div>
       </div>
 
+      {/* Firmware Compatibility */}
+      {device && describeMismatch(device) && (
+        <div style={{
+          padding: "10px 15px",
+          border: "1px solid #ffc107",
+          borderRadius: "8px",
+          backgroundColor: "#fff8e1",
+          color: "#664d03",
+          margin: "20px 0"
+        }}>
+          {describeMismatch(device)}
+        </div>
+      )}
+
+      {/* Firmware Commands */}
+      {isListening && device?.info && device.info.command_info.length > 0 && (
+        <div style={{
+          padding: "15px",
+          border: "1px solid #ddd",
+          borderRadius: "8px",
+          backgroundColor: "#f8f9fa",
+          margin: "20px 0"
+        }}>
+          <h3>Firmware Commands</h3>
+          <ul style={{ listStyle: "none", padding: 0, margin: 0 }}>
+            {device.info.command_info.map(command => (
+              <li key={command.name} style={{ display: "flex", alignItems: "center", gap: "10px", padding: "4px 0" }}>
+                <button
+                  onClick={() => sendDiscoveredCommand(command)}
+                  disabled={command.params !== null}
+                  title={command.params !== null ? "パラメータが必要なコマンドです" : undefined}
+                  style={{
+                    padding: "4px 12px",
+                    fontSize: "14px",
+                    minWidth: "90px",
+                    border: "1px solid #ccc",
+                    borderRadius: "4px",
+                    cursor: command.params !== null ? "not-allowed" : "pointer"
+                  }}
+                >
+                  {command.name}
+                </button>
+                <span style={{ fontSize: "14px", color: "#333" }}>{command.description}</span>
+                {command.privilege !== "public" && (
+                  <span style={{ fontSize: "12px", color: "#664d03" }}>🔒 {command.privilege}</span>
+                )}
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+
+      {/* Device Status */}
+      {isListening && deviceStatus && (
+        <div style={{
+          padding: "15px",
+          border: "1px solid #ddd",
+          borderRadius: "8px",
+          backgroundColor: "#f8f9fa",
+          margin: "20px 0",
+          fontSize: "14px",
+          textAlign: "left"
+        }}>
+          <h3>Device Status</h3>
+          <div>Firmware v{deviceStatus.firmware_version} ・ ESP-IDF {deviceStatus.idf_version}</div>
+          <div>Chip: {deviceStatus.chip.model} rev {deviceStatus.chip.revision} ({deviceStatus.chip.cores} cores)</div>
+          <div>Uptime: {formatUptime(deviceStatus.uptime_ms)}</div>
+          <div style={{ color: ABNORMAL_RESET_REASONS.includes(deviceStatus.reset_reason) ? "#b02a37" : "#333" }}>
+            Last reset: {deviceStatus.reset_reason}
+          </div>
+          <div>Heap: {deviceStatus.heap.free_bytes} bytes free (min {deviceStatus.heap.min_free_bytes} bytes)</div>
+          <div>Session: {deviceStatus.secure_session ? `🔐 ${deviceStatus.cipher_suite}` : "なし"}</div>
+          {deviceStatus.tasks.length > 0 && (
+            <div>
+              Stack high-water marks: {deviceStatus.tasks.map(task => `${task.name} ${task.stack_high_water_mark} bytes`).join(", ")}
+            </div>
+          )}
+        </div>
+      )}
+
       {/* Message Display */}
       <div style={{ 
         padding: "20px", 
@@ -241,7 +389,7 @@ function App() {
           margin: "30px 0"
         }}>
           <button 
-            onClick={() => sendCommand("hello")}
+            onClick={() => sendRequest({ action: "hello" })}
             style={{ 
               padding: "20px 50px", 
               fontSize: "24px",
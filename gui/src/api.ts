@@ -0,0 +1,67 @@
+// Tauriコマンドとイベントの型付きラッパー
+//
+// 型は shared_crypto と src-tauri/src/main.rs の with_commands! から生成した ./bindings を使う
+// （src-tauri の `UPDATE_BINDINGS=1 cargo test` で再生成、更新漏れは `cargo test` で検出される）。
+// 引数名は #[tauri::command] の引数の camelCase と対応させること。
+import { invoke, type InvokeArgs } from "@tauri-apps/api/core";
+import { listen, type EventCallback, type UnlistenFn } from "@tauri-apps/api/event";
+
+import type { CipherSuite } from "./bindings/CipherSuite";
+import type { CommandName } from "./bindings/CommandName";
+import type { DeviceState } from "./bindings/DeviceState";
+import type { EncryptedMessage } from "./bindings/EncryptedMessage";
+import type { Request } from "./bindings/Request";
+import type { Response } from "./bindings/Response";
+import type { JsonValue } from "./bindings/serde_json/JsonValue";
+
+// Command の action 以外の引数（binary はバイト値の配列で渡す）
+export interface CommandArgs {
+  data?: string;
+  params?: JsonValue;
+  binary?: number[];
+}
+
+// 登録されていないコマンド名は型エラーになる
+const call = <T>(command: CommandName, args?: InvokeArgs) => invoke<T>(command, args);
+
+export const listSerialPorts = () => call<string[]>("list_serial_ports");
+
+export const startSerialListener = (portName: string) =>
+  call<void>("start_serial_listener", { portName });
+
+export const getMessage = () => call<string | null>("get_message");
+
+// 戻り値は応答の id と対応するリクエストID
+export const sendRequest = (request: Request, encrypted = false) =>
+  call<number>("send_request", { request, encrypted });
+
+export const sendCommand = (action: string, args: CommandArgs = {}) =>
+  call<string>("send_command", { action, ...args });
+
+export const sendLightweightEncryptedCommand = (action: string, args: CommandArgs = {}) =>
+  call<string>("send_lightweight_encrypted_command", { action, ...args });
+
+export const initializeLightweightCrypto = () => call<string>("initialize_lightweight_crypto");
+
+export const decryptReceivedMessage = (encrypted: EncryptedMessage) =>
+  call<string>("decrypt_received_message", { encrypted });
+
+export const testBidirectionalCommunication = () => call<string>("test_bidirectional_communication");
+
+// バックエンドが発行するイベントとペイロード
+export interface EventPayloads {
+  "response-received": Response;
+  "encrypted-message-received": EncryptedMessage;
+  "device-log": string;
+  "device-info": DeviceState;
+  "device-mismatch": DeviceState;
+  "handshake-completed": CipherSuite;
+  "handshake-failed": string;
+  // ESP32の再起動でセッションが破棄され、ハンドシェイクをやり直す
+  "session-reset": null;
+}
+
+export const listenTo = <K extends keyof EventPayloads>(
+  event: K,
+  handler: EventCallback<EventPayloads[K]>,
+): Promise<UnlistenFn> => listen<EventPayloads[K]>(event, handler);
@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * チップの情報
+ */
+export type ChipInfo = { 
+/**
+ * チップのモデル（`"ESP32-S3"` など）
+ */
+model: string, 
+/**
+ * チップのリビジョン
+ */
+revision: number, 
+/**
+ * CPUコア数
+ */
+cores: number, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * AEAD暗号スイート
+ */
+export type CipherSuite = "aes-256-gcm" | "chacha20-poly1305" | "xchacha20-poly1305";
@@ -0,0 +1,32 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * コマンド構造体（ESP32-Tauri通信用）
+ *
+ * 引数は用途に応じて `params`（構造化されたパラメータ）と `binary`（バイト列）で渡します。
+ * `data` は従来形式の文字列で、互換性のために残しています。
+ */
+export type Command = { 
+/**
+ * アクション名
+ */
+action: string, 
+/**
+ * オプションのデータ（従来形式の文字列）
+ */
+data: string | null, 
+/**
+ * リクエストID（レスポンスとの対応付け用、省略可）
+ */
+id: number | null, 
+/**
+ * 構造化されたパラメータ（JSONオブジェクト）
+ */
+params: JsonValue | null, 
+/**
+ * バイナリデータ（設定データ・ファイルの断片・I2Cの書き込みデータなど）
+ *
+ * JSONではBase64文字列、CBORではバイト列のまま格納されます。
+ */
+binary: string | null, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Privilege } from "./Privilege";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * デバイスが報告するコマンドの説明
+ */
+export type CommandInfo = { 
+/**
+ * アクション名
+ */
+name: string, 
+/**
+ * 説明
+ */
+description: string, 
+/**
+ * パラメータのJSON Schema（パラメータがない場合は `None`）
+ */
+params: JsonValue | null, 
+/**
+ * 実行に必要な権限
+ */
+privilege: Privilege, };
@@ -0,0 +1,3 @@
+// This file was generated from `with_commands!` in src-tauri/src/main.rs. Do not edit this file manually.
+
+export type CommandName = "list_serial_ports" | "start_serial_listener" | "send_command" | "send_request" | "get_message" | "initialize_lightweight_crypto" | "decrypt_received_message" | "send_lightweight_encrypted_command" | "test_bidirectional_communication";
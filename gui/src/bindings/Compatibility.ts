@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ホストとデバイスの互換性
+ */
+export type Compatibility = { "level": "compatible" } | { "level": "degraded", 
+/**
+ * デバイスが処理できないアクション
+ */
+missing_commands: Array<string>, 
+/**
+ * 共通の暗号スイートがあり、暗号化通信が使えるか
+ */
+encryption_available: boolean, } | { "level": "incompatible", 
+/**
+ * ホストのプロトコルバージョン
+ */
+host_protocol: number, 
+/**
+ * デバイスのプロトコルバージョン
+ */
+device_protocol: number, };
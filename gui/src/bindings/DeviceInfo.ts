@@ -0,0 +1,32 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CipherSuite } from "./CipherSuite";
+import type { CommandInfo } from "./CommandInfo";
+
+/**
+ * ファームウェアが報告する機能情報
+ */
+export type DeviceInfo = { 
+/**
+ * プロトコルバージョン（[`PROTOCOL_VERSION`]）
+ */
+protocol_version: number, 
+/**
+ * ファームウェアのバージョン
+ */
+firmware_version: string, 
+/**
+ * ビルド時のコミットハッシュ（取得できない場合は `"unknown"`）
+ */
+build_hash: string, 
+/**
+ * 処理できるアクション名
+ */
+commands: Array<string>, 
+/**
+ * ハンドシェイクで受け入れる暗号スイート（優先順）
+ */
+cipher_suites: Array<CipherSuite>, 
+/**
+ * 処理できるコマンドの説明（報告しない旧ファームウェアでは空）
+ */
+command_info: Array<CommandInfo>, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Compatibility } from "./Compatibility";
+import type { DeviceInfo } from "./DeviceInfo";
+
+export type DeviceState = { 
+/**
+ * ファームウェアが報告した機能情報（旧ファームウェアでは `None`）
+ */
+info: DeviceInfo | null, 
+/**
+ * ホストとの互換性（機能情報がなければ `None`）
+ */
+compatibility: Compatibility | null, };
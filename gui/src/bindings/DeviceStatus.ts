@@ -0,0 +1,47 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChipInfo } from "./ChipInfo";
+import type { CipherSuite } from "./CipherSuite";
+import type { HeapStats } from "./HeapStats";
+import type { ResetReason } from "./ResetReason";
+import type { TaskStack } from "./TaskStack";
+
+/**
+ * `status` リクエストの応答のペイロード
+ */
+export type DeviceStatus = { 
+/**
+ * セッション鍵が確立しているか
+ */
+secure_session: boolean, 
+/**
+ * 確立したセッション鍵の暗号スイート（ハンドシェイク前は `None`）
+ */
+cipher_suite: CipherSuite | null, 
+/**
+ * ファームウェアのバージョン
+ */
+firmware_version: string, 
+/**
+ * ESP-IDFのバージョン（ホストのシミュレータでは `"host"`）
+ */
+idf_version: string, 
+/**
+ * チップの情報
+ */
+chip: ChipInfo, 
+/**
+ * 起動からの経過時間（ミリ秒）
+ */
+uptime_ms: number, 
+/**
+ * 直前のリセット要因
+ */
+reset_reason: ResetReason, 
+/**
+ * ヒープの使用状況
+ */
+heap: HeapStats, 
+/**
+ * タスクごとのスタックの余裕
+ */
+tasks: Array<TaskStack>, };
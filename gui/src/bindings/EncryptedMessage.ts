@@ -0,0 +1,29 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CipherSuite } from "./CipherSuite";
+
+/**
+ * 暗号化されたメッセージを表す構造体
+ *
+ * JSONでは `ciphertext` と `nonce` はBase64文字列、CBORではバイト列になります。
+ */
+export type EncryptedMessage = { 
+/**
+ * 暗号文（認証タグを含む）
+ */
+ciphertext: string, 
+/**
+ * nonce（初期化ベクトル）
+ */
+nonce: string, 
+/**
+ * 送信側のシーケンス番号（1から単調増加、認証データに含まれる）
+ */
+seq: number, 
+/**
+ * 暗号化に使用した鍵のID（認証データに含まれる）
+ */
+key_id: number, 
+/**
+ * 暗号化に使用した暗号スイート（認証データに含まれる）
+ */
+suite: CipherSuite, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * エラーレスポンスの機械可読なエラーコード
+ */
+export type ErrorCode = "unknown_command" | "invalid_parameters" | "handshake_failed" | "decryption_failed" | "encryption_required" | "permission_denied" | "rate_limited" | "unsupported" | "transport_error" | "internal";
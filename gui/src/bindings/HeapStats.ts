@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ヒープの使用状況
+ */
+export type HeapStats = { 
+/**
+ * 現在の空きヒープ（バイト）
+ */
+free_bytes: number, 
+/**
+ * 起動してからの空きヒープの最小値（バイト）
+ */
+min_free_bytes: number, };
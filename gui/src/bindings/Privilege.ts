@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * コマンドの実行に必要な権限
+ */
+export type Privilege = "public" | "authenticated" | "session";
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ESP32へのリクエスト
+ *
+ * アクション名は serde のタグと strum の `serialize_all` の両方で同じ snake_case にしています。
+ */
+export type Request = { "action": "hello" } | { "action": "ping" } | { "action": "status" } | { "action": "info" } | { "action": "echo", 
+/**
+ * 返してほしいテキスト
+ */
+text: string, } | { "action": "reboot", 
+/**
+ * 再起動までの待ち時間（ミリ秒）
+ */
+delay_ms: number, };
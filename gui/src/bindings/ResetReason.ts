@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 直前のリセット要因
+ */
+export type ResetReason = "power_on" | "external" | "software" | "panic" | "interrupt_watchdog" | "task_watchdog" | "watchdog" | "deep_sleep" | "brownout" | "sdio" | "usb" | "jtag" | "efuse" | "power_glitch" | "cpu_lockup" | "unknown";
@@ -0,0 +1,35 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ErrorCode } from "./ErrorCode";
+import type { ResponseStatus } from "./ResponseStatus";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * レスポンス構造体（ESP32-Tauri通信用）
+ *
+ * 処理結果は `status` と `code` で判定し、`message` は表示用として扱います。
+ */
+export type Response = { 
+/**
+ * ステータス
+ */
+status: ResponseStatus, 
+/**
+ * メッセージ内容（表示用）
+ */
+message: string, 
+/**
+ * 応答元のコマンド
+ */
+response_to: string | null, 
+/**
+ * 応答元のコマンドのリクエストID（起動通知など要求に対応しない場合は `None`）
+ */
+id: number | null, 
+/**
+ * エラーコード（`status` が `Error` の場合）
+ */
+code: ErrorCode | null, 
+/**
+ * 構造化されたペイロード（リクエストごとの結果）
+ */
+payload: JsonValue | null, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * タスクのスタックの余裕
+ */
+export type TaskStack = { 
+/**
+ * タスク名
+ */
+name: string, 
+/**
+ * 起動してからのスタックの空きの最小値（バイト）
+ */
+stack_high_water_mark: number, };
@@ -0,0 +1,174 @@
+//! # 非同期セキュアチャネル
+//!
+//! [`SecureChannel`](crate::channel::SecureChannel) のtokio版です（`async` 機能、`tauri` 機能で有効）。
+//! `AsyncRead + AsyncWrite` を実装するトランスポート（tokio-serial、pty、TCPなど）の上で、
+//! 同じフレーミング・コーデック・暗号化システムを使ってメッセージを送受信します。
+//!
+//! 読み取りにタイムアウトはないため、必要に応じて `tokio::time::timeout` で包んでください。
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::channel::{encode_packet, ChannelError, ChannelMessage, Inbox, Packet, READ_BUFFER_LEN};
+use crate::framing::DEFAULT_MAX_FRAME_LEN;
+use crate::{CryptoSystem, EncryptedMessage};
+
+/// 暗号化されたメッセージを非同期に送受信するチャネル
+pub struct AsyncSecureChannel<T> {
+    /// 下位のトランスポート
+    transport: T,
+    /// セッションの暗号化システム（コーデックもここから取得）
+    crypto: CryptoSystem,
+    /// 送受信するフレームの最大ペイロード長
+    max_frame_len: usize,
+    /// 受信バッファ
+    inbox: Inbox,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncSecureChannel<T> {
+    /// トランスポートと暗号化システムからチャネルを作成
+    ///
+    /// # 引数
+    /// * `transport` - 送受信に使うトランスポート
+    /// * `crypto` - セッションの暗号化システム（同期版のチャネルと共有可能）
+    pub fn new(transport: T, crypto: CryptoSystem) -> Self {
+        Self {
+            transport,
+            crypto,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            inbox: Inbox::default(),
+        }
+    }
+
+    /// フレームの最大ペイロード長を設定（デフォルトは [`DEFAULT_MAX_FRAME_LEN`]）
+    ///
+    /// 相手のデコーダと同じ値にしてください。これを超えるパケットは送信時に `FrameTooLarge` になります。
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self.inbox = Inbox::with_max_frame_len(max_frame_len);
+        self
+    }
+
+    /// フレームの最大ペイロード長
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    /// 暗号化システムを取得
+    pub fn crypto(&self) -> &CryptoSystem {
+        &self.crypto
+    }
+
+    /// 暗号化システムを置き換え（ハンドシェイク完了時や鍵の更新時）
+    pub fn set_crypto(&mut self, crypto: CryptoSystem) {
+        self.crypto = crypto;
+    }
+
+    /// トランスポートへの参照を取得
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// トランスポートへの可変参照を取得
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// チャネルを分解してトランスポートを取り出す
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// メッセージを暗号化して送信
+    pub async fn send<M: ChannelMessage>(&mut self, message: &M) -> Result<(), ChannelError> {
+        let encrypted = message.seal(&self.crypto)?;
+        self.send_packet(&Packet::Encrypted(encrypted)).await
+    }
+
+    /// パケットをそのまま送信（ハンドシェイクや平文メッセージ用）
+    ///
+    /// # エラー
+    /// シリアライズしたパケットが最大ペイロード長を超える場合は `ChannelError::FrameTooLarge`
+    pub async fn send_packet(&mut self, packet: &Packet) -> Result<(), ChannelError> {
+        let frame = encode_packet(&self.crypto, packet, self.max_frame_len)?;
+        self.transport.write_all(&frame).await?;
+        self.transport.flush().await?;
+        Ok(())
+    }
+
+    /// 暗号化されたメッセージを受信して復号化
+    ///
+    /// # エラー
+    /// 暗号化されていないパケットを受信した場合は `ChannelError::UnexpectedPacket`
+    pub async fn recv<M: ChannelMessage>(&mut self) -> Result<M, ChannelError> {
+        match self.recv_packet().await? {
+            Packet::Encrypted(encrypted) => self.open(&encrypted),
+            packet => Err(ChannelError::UnexpectedPacket(packet)),
+        }
+    }
+
+    /// 受信した暗号化メッセージを復号化（リプレイ検出を含む）
+    pub fn open<M: ChannelMessage>(&self, encrypted: &EncryptedMessage) -> Result<M, ChannelError> {
+        Ok(M::open(&self.crypto, encrypted)?)
+    }
+
+    /// 次のパケットを受信
+    ///
+    /// 破損したフレームとフレーム外のデータは読み飛ばし、[`AsyncSecureChannel::take_discarded`] で取得できます。
+    /// 読み取ったデータは受信バッファに残るため、`tokio::select!` で送信と並行して待ってもパケットは失われません。
+    pub async fn recv_packet(&mut self) -> Result<Packet, ChannelError> {
+        loop {
+            if let Some(packet) = self.inbox.next_packet(&self.crypto) {
+                return Ok(packet);
+            }
+
+            let mut buffer = [0u8; READ_BUFFER_LEN];
+            let bytes_read = self.transport.read(&mut buffer).await?;
+            if bytes_read == 0 {
+                return Err(ChannelError::Closed);
+            }
+            self.inbox.feed(&buffer[..bytes_read]);
+        }
+    }
+
+    /// これまでに読み飛ばしたデータを取り出す
+    pub fn take_discarded(&mut self) -> Vec<Vec<u8>> {
+        self.inbox.take_discarded()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::SecureChannel;
+    use crate::{Command, Response, Role, WireCodec};
+
+    #[tokio::test]
+    async fn test_request_response_over_duplex() {
+        let (host_io, device_io) = tokio::io::duplex(1024);
+        let mut host = AsyncSecureChannel::new(host_io, CryptoSystem::from_key([1u8; 32], Role::Host).with_codec(WireCodec::Cbor));
+        let mut device = AsyncSecureChannel::new(device_io, CryptoSystem::from_key([1u8; 32], Role::Device).with_codec(WireCodec::Cbor));
+
+        let device_task = tokio::spawn(async move {
+            let command: Command = device.recv().await.unwrap();
+            device.send(&Response::ok("pong").in_reply_to(&command)).await.unwrap();
+        });
+
+        host.send(&Command::new("ping").with_id(1)).await.unwrap();
+        let response: Response = host.recv().await.unwrap();
+        assert_eq!(response.response_to.as_deref(), Some("ping"));
+        assert_eq!(response.id, Some(1));
+        device_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_compatible_with_blocking_channel() {
+        let mut blocking = SecureChannel::new(std::io::Cursor::new(Vec::new()), CryptoSystem::from_key([1u8; 32], Role::Host));
+        blocking.send(&Command::new("status")).unwrap();
+        let sent = blocking.into_inner().into_inner();
+
+        let mut device = AsyncSecureChannel::new(std::io::Cursor::new(sent), CryptoSystem::from_key([1u8; 32], Role::Device));
+        let command: Command = device.recv().await.unwrap();
+        assert_eq!(command.action, "status");
+        assert!(matches!(device.recv::<Command>().await, Err(ChannelError::Closed)));
+    }
+}
@@ -0,0 +1,281 @@
+//! # 接続時の機能ネゴシエーション
+//!
+//! ESP32は起動通知（`ready` イベント）と `info` リクエストの応答のペイロードで
+//! [`DeviceInfo`] を報告します。ホストは [`DeviceInfo::check_compatibility`] で
+//! 自身と比較し、互換性がなければ送信を拒否、一部の機能が欠けていれば縮退して動作します。
+//! 各コマンドの説明・パラメータ・必要な権限は [`CommandInfo`] で報告されます。
+//!
+//! ```rust
+//! use esp32_tauri_crypto::{CipherSuite, Compatibility, DeviceInfo};
+//!
+//! let info = DeviceInfo::new("0.1.0", "abc1234", &[CipherSuite::ChaCha20Poly1305]);
+//! assert_eq!(info.check_compatibility(&[CipherSuite::ChaCha20Poly1305]), Compatibility::Compatible);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use strum::{IntoStaticStr, VariantArray};
+
+use crate::{CipherSuite, Request, PROTOCOL_VERSION};
+
+/// ファームウェアが報告する機能情報
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct DeviceInfo {
+    /// プロトコルバージョン（[`PROTOCOL_VERSION`]）
+    pub protocol_version: u8,
+    /// ファームウェアのバージョン
+    pub firmware_version: String,
+    /// ビルド時のコミットハッシュ（取得できない場合は `"unknown"`）
+    pub build_hash: String,
+    /// 処理できるアクション名
+    pub commands: Vec<String>,
+    /// ハンドシェイクで受け入れる暗号スイート（優先順）
+    pub cipher_suites: Vec<CipherSuite>,
+    /// 処理できるコマンドの説明（報告しない旧ファームウェアでは空）
+    #[serde(default)]
+    pub command_info: Vec<CommandInfo>,
+}
+
+impl DeviceInfo {
+    /// このビルドの共通ライブラリで定義されたリクエストを処理できるデバイスの情報を作成
+    ///
+    /// # 引数
+    /// * `firmware_version` - ファームウェアのバージョン
+    /// * `build_hash` - ビルド時のコミットハッシュ
+    /// * `cipher_suites` - ハンドシェイクで受け入れる暗号スイート（優先順）
+    pub fn new(firmware_version: impl Into<String>, build_hash: impl Into<String>, cipher_suites: &[CipherSuite]) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            firmware_version: firmware_version.into(),
+            build_hash: build_hash.into(),
+            commands: Request::ACTIONS.iter().map(|action| action.to_string()).collect(),
+            cipher_suites: cipher_suites.to_vec(),
+            command_info: Vec::new(),
+        }
+    }
+
+    /// 処理できるコマンドを変更
+    ///
+    /// `commands` にはそれぞれのコマンド名が設定されます。
+    pub fn with_commands(mut self, commands: Vec<CommandInfo>) -> Self {
+        self.commands = commands.iter().map(|command| command.name.clone()).collect();
+        self.command_info = commands;
+        self
+    }
+
+    /// アクションを処理できるか
+    pub fn supports(&self, action: &str) -> bool {
+        self.commands.iter().any(|command| command == action)
+    }
+
+    /// コマンドの説明
+    pub fn command(&self, action: &str) -> Option<&CommandInfo> {
+        self.command_info.iter().find(|command| command.name == action)
+    }
+
+    /// ホストとの互換性を判定
+    ///
+    /// # 引数
+    /// * `offered_suites` - ホストがハンドシェイクで提示する暗号スイート
+    ///
+    /// # 戻り値
+    /// プロトコルバージョンが異なる場合は `Incompatible`、
+    /// ホストの定義するリクエストの一部を処理できない・共通の暗号スイートがない場合は `Degraded`
+    pub fn check_compatibility(&self, offered_suites: &[CipherSuite]) -> Compatibility {
+        if self.protocol_version != PROTOCOL_VERSION {
+            return Compatibility::Incompatible {
+                host_protocol: PROTOCOL_VERSION,
+                device_protocol: self.protocol_version,
+            };
+        }
+
+        let missing_commands: Vec<String> = Request::ACTIONS
+            .iter()
+            .filter(|action| !self.supports(action))
+            .map(|action| action.to_string())
+            .collect();
+        let encryption_available = offered_suites.iter().any(|suite| self.cipher_suites.contains(suite));
+
+        if missing_commands.is_empty() && encryption_available {
+            Compatibility::Compatible
+        } else {
+            Compatibility::Degraded { missing_commands, encryption_available }
+        }
+    }
+}
+
+/// コマンドの実行に必要な権限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoStaticStr, VariantArray)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Privilege {
+    /// 平文でも実行できる
+    #[default]
+    Public,
+    /// 暗号化されたコマンド（PSKまたはセッション鍵）のみ
+    Authenticated,
+    /// ハンドシェイクで確立したセッション鍵で暗号化されたコマンドのみ
+    Session,
+}
+
+impl Privilege {
+    /// すべての権限
+    pub const ALL: &'static [Privilege] = <Self as VariantArray>::VARIANTS;
+
+    /// ワイヤー上の名前
+    pub fn as_str(&self) -> &'static str {
+        self.into()
+    }
+
+    /// コマンドを実行できるか
+    ///
+    /// # 引数
+    /// * `encrypted` - コマンドが暗号化されていたか
+    /// * `secure_session` - セッション鍵が確立しているか
+    pub fn allows(&self, encrypted: bool, secure_session: bool) -> bool {
+        match self {
+            Privilege::Public => true,
+            Privilege::Authenticated => encrypted,
+            Privilege::Session => encrypted && secure_session,
+        }
+    }
+}
+
+impl std::fmt::Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// デバイスが報告するコマンドの説明
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct CommandInfo {
+    /// アクション名
+    pub name: String,
+    /// 説明
+    pub description: String,
+    /// パラメータのJSON Schema（パラメータがない場合は `None`）
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+    /// 実行に必要な権限
+    #[serde(default)]
+    pub privilege: Privilege,
+}
+
+impl CommandInfo {
+    /// パラメータがなく、平文でも実行できるコマンドの説明を作成
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            params: None,
+            privilege: Privilege::Public,
+        }
+    }
+
+    /// パラメータのJSON Schemaを設定
+    pub fn with_params(mut self, schema: serde_json::Value) -> Self {
+        self.params = Some(schema);
+        self
+    }
+
+    /// 必要な権限を設定
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+}
+
+/// ホストとデバイスの互換性
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(tag = "level", rename_all = "snake_case")]
+pub enum Compatibility {
+    /// すべての機能が使える
+    Compatible,
+    /// 一部の機能が使えない（使える機能だけで動作を続ける）
+    Degraded {
+        /// デバイスが処理できないアクション
+        missing_commands: Vec<String>,
+        /// 共通の暗号スイートがあり、暗号化通信が使えるか
+        encryption_available: bool,
+    },
+    /// 通信できない（送信を拒否する）
+    Incompatible {
+        /// ホストのプロトコルバージョン
+        host_protocol: u8,
+        /// デバイスのプロトコルバージョン
+        device_protocol: u8,
+    },
+}
+
+impl Compatibility {
+    /// デバイスとの通信を続けられるか
+    pub fn is_usable(&self) -> bool {
+        !matches!(self, Compatibility::Incompatible { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_older_firmware_is_degraded() {
+        let mut info = DeviceInfo::new("0.0.9", "deadbee", &[CipherSuite::Aes256Gcm]);
+        info.commands.retain(|command| command != "echo");
+
+        match info.check_compatibility(&[CipherSuite::XChaCha20Poly1305]) {
+            Compatibility::Degraded { missing_commands, encryption_available } => {
+                assert_eq!(missing_commands, vec!["echo".to_string()]);
+                assert!(!encryption_available);
+            }
+            other => panic!("unexpected compatibility: {:?}", other),
+        }
+        assert!(!info.supports("echo"));
+    }
+
+    #[test]
+    fn test_protocol_mismatch_is_incompatible() {
+        let mut info = DeviceInfo::new("9.0.0", "unknown", &CipherSuite::ALL);
+        info.protocol_version = PROTOCOL_VERSION + 1;
+
+        let compatibility = info.check_compatibility(&CipherSuite::ALL);
+        assert!(!compatibility.is_usable());
+
+        // GUIへのイベントとして送れる形式
+        let json = serde_json::to_value(&compatibility).unwrap();
+        assert_eq!(json["level"], "incompatible");
+        assert_eq!(json["device_protocol"], PROTOCOL_VERSION + 1);
+    }
+
+    #[test]
+    fn test_command_info_is_reported_with_names() {
+        let info = DeviceInfo::new("0.2.0", "abc1234", &CipherSuite::ALL).with_commands(vec![
+            CommandInfo::new("ping", "Reply with pong"),
+            CommandInfo::new("unlock", "Unlock the door").with_privilege(Privilege::Session),
+        ]);
+        assert_eq!(info.commands, vec!["ping".to_string(), "unlock".to_string()]);
+
+        let unlock = info.command("unlock").unwrap();
+        assert!(!unlock.privilege.allows(true, false));
+        assert!(unlock.privilege.allows(true, true));
+
+        // 説明を報告しない旧ファームウェアの機能情報も読み込める
+        let mut json = serde_json::to_value(&info).unwrap();
+        json.as_object_mut().unwrap().remove("command_info");
+        let legacy: DeviceInfo = serde_json::from_value(json).unwrap();
+        assert!(legacy.command("ping").is_none());
+        assert!(legacy.supports("ping"));
+    }
+
+    #[test]
+    fn test_privilege_names_match_serde() {
+        for &privilege in Privilege::ALL {
+            assert_eq!(serde_json::to_value(privilege).unwrap(), serde_json::json!(privilege.as_str()));
+            assert_eq!(serde_json::from_value::<Privilege>(serde_json::json!(privilege.as_str())).unwrap(), privilege);
+        }
+    }
+}
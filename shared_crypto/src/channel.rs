@@ -0,0 +1,463 @@
+//! # セキュアチャネル
+//!
+//! 任意の `Read + Write` トランスポート（シリアルポート、コンソール、pty）の上で、
+//! フレーミング・暗号化・リプレイ検出・シリアライズをまとめて行います。
+//!
+//! フレームには [`Packet`] を格納します。
+//! ハンドシェイクと平文のコマンド・レスポンスも同じ形式で送受信できます。
+//!
+//! ```rust,no_run
+//! use esp32_tauri_crypto::channel::{ChannelError, SecureChannel};
+//! use esp32_tauri_crypto::{Command, CryptoSystem, Response};
+//! use std::io::{Read, Write};
+//!
+//! fn ping(port: impl Read + Write, crypto: CryptoSystem) -> Result<Response, ChannelError> {
+//!     let mut channel = SecureChannel::new(port, crypto);
+//!
+//!     channel.send(&Command::new("ping"))?;
+//!     channel.recv()
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::framing::{encode_frame, FrameDecoder, FrameEvent, DEFAULT_MAX_FRAME_LEN};
+use crate::handshake::HandshakeMessage;
+use crate::{Command, CryptoError, CryptoSystem, EncryptedMessage, Response, WireCodec};
+
+/// 1回の読み取りで使うバッファサイズ
+pub(crate) const READ_BUFFER_LEN: usize = 256;
+
+/// フレームに格納するパケット
+///
+/// JSONでは `{"type":"encrypted","body":{...}}` の形式になります。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "body", rename_all = "snake_case")]
+pub enum Packet {
+    /// ハンドシェイクメッセージ（平文、MACで認証）
+    Handshake(HandshakeMessage),
+    /// 暗号化されたコマンドまたはレスポンス
+    Encrypted(EncryptedMessage),
+    /// 平文のコマンド
+    Command(Command),
+    /// 平文のレスポンス
+    Response(Response),
+}
+
+/// チャネルで暗号化して送受信できるメッセージ
+///
+/// コマンドとレスポンスは別の種別ヘッダで暗号化されるため、取り違えると復号化に失敗します。
+pub trait ChannelMessage: Sized {
+    /// メッセージを暗号化
+    fn seal(&self, crypto: &CryptoSystem) -> Result<EncryptedMessage, CryptoError>;
+
+    /// 暗号化されたメッセージを復号化
+    fn open(crypto: &CryptoSystem, encrypted: &EncryptedMessage) -> Result<Self, CryptoError>;
+}
+
+impl ChannelMessage for Command {
+    fn seal(&self, crypto: &CryptoSystem) -> Result<EncryptedMessage, CryptoError> {
+        crypto.encrypt_command(self)
+    }
+
+    fn open(crypto: &CryptoSystem, encrypted: &EncryptedMessage) -> Result<Self, CryptoError> {
+        crypto.decrypt_to_command(encrypted)
+    }
+}
+
+impl ChannelMessage for Response {
+    fn seal(&self, crypto: &CryptoSystem) -> Result<EncryptedMessage, CryptoError> {
+        crypto.encrypt_response(self)
+    }
+
+    fn open(crypto: &CryptoSystem, encrypted: &EncryptedMessage) -> Result<Self, CryptoError> {
+        crypto.decrypt_to_response(encrypted)
+    }
+}
+
+/// チャネルのエラー型
+#[derive(Debug)]
+pub enum ChannelError {
+    /// トランスポートの入出力エラー
+    Io(std::io::Error),
+    /// 読み取りがタイムアウトした（データ待ち）
+    Timeout,
+    /// トランスポートが閉じられた
+    Closed,
+    /// 暗号化・復号化・シリアライズのエラー
+    Crypto(CryptoError),
+    /// 暗号化メッセージ以外のパケットを受信した
+    UnexpectedPacket(Packet),
+    /// 送信するフレームが相手の最大ペイロード長を超えている
+    FrameTooLarge {
+        /// シリアライズしたパケットの長さ
+        len: usize,
+        /// 最大ペイロード長
+        max: usize,
+    },
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelError::Io(e) => write!(f, "通信エラー: {}", e),
+            ChannelError::Timeout => write!(f, "受信がタイムアウトしました"),
+            ChannelError::Closed => write!(f, "接続が閉じられました"),
+            ChannelError::Crypto(e) => write!(f, "{}", e),
+            ChannelError::UnexpectedPacket(_) => write!(f, "暗号化されていないパケットを受信しました"),
+            ChannelError::FrameTooLarge { len, max } => write!(f, "フレームが大きすぎます（{}バイト、上限{}バイト）", len, max),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+impl From<std::io::Error> for ChannelError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => ChannelError::Timeout,
+            ErrorKind::UnexpectedEof | ErrorKind::BrokenPipe => ChannelError::Closed,
+            _ => ChannelError::Io(e),
+        }
+    }
+}
+
+impl From<CryptoError> for ChannelError {
+    fn from(e: CryptoError) -> Self {
+        ChannelError::Crypto(e)
+    }
+}
+
+/// パケットをコーデックでシリアライズしてフレーム化
+///
+/// 相手のデコーダは `max_frame_len` を超えるフレームを破棄するため、送信前にエラーにします。
+pub(crate) fn encode_packet(crypto: &CryptoSystem, packet: &Packet, max_frame_len: usize) -> Result<Vec<u8>, ChannelError> {
+    let payload = crypto.codec().encode(packet)?;
+    if payload.len() > max_frame_len {
+        return Err(ChannelError::FrameTooLarge { len: payload.len(), max: max_frame_len });
+    }
+    Ok(encode_frame(&payload))
+}
+
+/// 受信側のフレーム・パケットのバッファ（同期・非同期チャネルで共通）
+#[derive(Debug, Default)]
+pub(crate) struct Inbox {
+    /// 受信フレームのデコーダ
+    decoder: FrameDecoder,
+    /// デコード済みで未処理のフレーム
+    pending: VecDeque<Vec<u8>>,
+    /// フレーム外で受信したデータ（ログ出力など）
+    discarded: Vec<Vec<u8>>,
+}
+
+impl Inbox {
+    /// 最大ペイロード長を指定して作成
+    pub(crate) fn with_max_frame_len(max_frame_len: usize) -> Self {
+        Self {
+            decoder: FrameDecoder::new().with_max_frame_len(max_frame_len),
+            ..Self::default()
+        }
+    }
+
+    /// 読み取ったバイト列を投入
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        for event in self.decoder.feed(data) {
+            match event {
+                FrameEvent::Frame(payload) => self.pending.push_back(payload),
+                FrameEvent::Discarded(bytes) => self.discarded.push(bytes),
+            }
+        }
+    }
+
+    /// 次のパケットを取り出す（どのコーデックでもデシリアライズできないフレームは破棄データとして扱う）
+    ///
+    /// セッションのコーデックを優先し、失敗した場合は他のコーデックも試します。
+    /// 再起動直後のデバイスはセッション確立前のJSONで送信するため、CBORのセッション中でも受信できます。
+    pub(crate) fn next_packet(&mut self, crypto: &CryptoSystem) -> Option<Packet> {
+        while let Some(payload) = self.pending.pop_front() {
+            let codec = crypto.codec();
+            let packet = codec.decode(&payload).ok().or_else(|| {
+                WireCodec::ALL.iter()
+                    .filter(|other| **other != codec)
+                    .find_map(|other| other.decode(&payload).ok())
+            });
+            match packet {
+                Some(packet) => return Some(packet),
+                None => self.discarded.push(payload),
+            }
+        }
+        None
+    }
+
+    /// 破棄したデータを取り出す
+    pub(crate) fn take_discarded(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.discarded)
+    }
+}
+
+/// 暗号化されたメッセージを送受信するチャネル
+pub struct SecureChannel<T> {
+    /// 下位のトランスポート
+    transport: T,
+    /// セッションの暗号化システム（コーデックもここから取得）
+    crypto: CryptoSystem,
+    /// 送受信するフレームの最大ペイロード長
+    max_frame_len: usize,
+    /// 受信バッファ
+    inbox: Inbox,
+}
+
+impl<T: Read + Write> SecureChannel<T> {
+    /// トランスポートと暗号化システムからチャネルを作成
+    ///
+    /// # 引数
+    /// * `transport` - 送受信に使うトランスポート
+    /// * `crypto` - セッションの暗号化システム（送受信の両方で同じインスタンスを共有可能）
+    pub fn new(transport: T, crypto: CryptoSystem) -> Self {
+        Self {
+            transport,
+            crypto,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            inbox: Inbox::default(),
+        }
+    }
+
+    /// フレームの最大ペイロード長を設定（デフォルトは [`DEFAULT_MAX_FRAME_LEN`]）
+    ///
+    /// 相手のデコーダと同じ値にしてください。これを超えるパケットは送信時に `FrameTooLarge` になります。
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self.inbox = Inbox::with_max_frame_len(max_frame_len);
+        self
+    }
+
+    /// フレームの最大ペイロード長
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    /// 暗号化システムを取得
+    pub fn crypto(&self) -> &CryptoSystem {
+        &self.crypto
+    }
+
+    /// 暗号化システムを置き換え（ハンドシェイク完了時や鍵の更新時）
+    pub fn set_crypto(&mut self, crypto: CryptoSystem) {
+        self.crypto = crypto;
+    }
+
+    /// トランスポートへの参照を取得
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// トランスポートへの可変参照を取得
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// チャネルを分解してトランスポートを取り出す
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// メッセージを暗号化して送信
+    pub fn send<M: ChannelMessage>(&mut self, message: &M) -> Result<(), ChannelError> {
+        let encrypted = message.seal(&self.crypto)?;
+        self.send_packet(&Packet::Encrypted(encrypted))
+    }
+
+    /// パケットをそのまま送信（ハンドシェイクや平文メッセージ用）
+    ///
+    /// # エラー
+    /// シリアライズしたパケットが最大ペイロード長を超える場合は `ChannelError::FrameTooLarge`
+    pub fn send_packet(&mut self, packet: &Packet) -> Result<(), ChannelError> {
+        let frame = encode_packet(&self.crypto, packet, self.max_frame_len)?;
+        self.transport.write_all(&frame)?;
+        self.transport.flush()?;
+        Ok(())
+    }
+
+    /// 暗号化されたメッセージを受信して復号化
+    ///
+    /// # エラー
+    /// 暗号化されていないパケットを受信した場合は `ChannelError::UnexpectedPacket`。
+    /// 呼び出し側でハンドシェイクや平文メッセージとして処理できます。
+    pub fn recv<M: ChannelMessage>(&mut self) -> Result<M, ChannelError> {
+        match self.recv_packet()? {
+            Packet::Encrypted(encrypted) => self.open(&encrypted),
+            packet => Err(ChannelError::UnexpectedPacket(packet)),
+        }
+    }
+
+    /// 受信した暗号化メッセージを復号化（リプレイ検出を含む）
+    pub fn open<M: ChannelMessage>(&self, encrypted: &EncryptedMessage) -> Result<M, ChannelError> {
+        Ok(M::open(&self.crypto, encrypted)?)
+    }
+
+    /// 次のパケットを受信
+    ///
+    /// 破損したフレームとフレーム外のデータは読み飛ばし、[`SecureChannel::take_discarded`] で取得できます。
+    pub fn recv_packet(&mut self) -> Result<Packet, ChannelError> {
+        loop {
+            if let Some(packet) = self.inbox.next_packet(&self.crypto) {
+                return Ok(packet);
+            }
+
+            let mut buffer = [0u8; READ_BUFFER_LEN];
+            let bytes_read = match self.transport.read(&mut buffer) {
+                Ok(0) => return Err(ChannelError::Closed),
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            self.inbox.feed(&buffer[..bytes_read]);
+        }
+    }
+
+    /// これまでに読み飛ばしたデータを取り出す
+    pub fn take_discarded(&mut self) -> Vec<Vec<u8>> {
+        self.inbox.take_discarded()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ResponseStatus, Role};
+    use std::io::Cursor;
+
+    /// 書き込んだデータを保持し、与えたデータを読み出すトランスポート
+    struct MemoryTransport {
+        rx: Cursor<Vec<u8>>,
+        tx: Vec<u8>,
+    }
+
+    impl MemoryTransport {
+        fn new(rx: Vec<u8>) -> Self {
+            Self { rx: Cursor::new(rx), tx: Vec::new() }
+        }
+    }
+
+    impl Read for MemoryTransport {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for MemoryTransport {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn ping() -> Command {
+        Command::new("ping")
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        let mut host = SecureChannel::new(MemoryTransport::new(Vec::new()), CryptoSystem::from_key([1u8; 32], Role::Host));
+        host.send(&ping()).unwrap();
+
+        let sent = host.into_inner().tx;
+        let mut device = SecureChannel::new(MemoryTransport::new(sent), CryptoSystem::from_key([1u8; 32], Role::Device));
+        let command: Command = device.recv().unwrap();
+        assert_eq!(command.action, "ping");
+        assert!(matches!(device.recv::<Command>(), Err(ChannelError::Closed)));
+    }
+
+    #[test]
+    fn test_replayed_frame_is_rejected() {
+        let mut host = SecureChannel::new(MemoryTransport::new(Vec::new()), CryptoSystem::from_key([1u8; 32], Role::Host));
+        host.send(&ping()).unwrap();
+
+        let sent = host.into_inner().tx;
+        let mut replayed = sent.clone();
+        replayed.extend(&sent);
+
+        let mut device = SecureChannel::new(MemoryTransport::new(replayed), CryptoSystem::from_key([1u8; 32], Role::Device));
+        assert!(device.recv::<Command>().is_ok());
+        assert!(matches!(device.recv::<Command>(), Err(ChannelError::Crypto(CryptoError::ReplayDetected))));
+    }
+
+    #[test]
+    fn test_plain_packets_and_log_output() {
+        let mut device = SecureChannel::new(MemoryTransport::new(Vec::new()), CryptoSystem::from_key([1u8; 32], Role::Device));
+        device.send_packet(&Packet::Response(Response::event("ESP32 ready"))).unwrap();
+
+        let mut received = b"I (310) main_task: Calling app_main()\r\n".to_vec();
+        received.extend(device.into_inner().tx);
+
+        let mut host = SecureChannel::new(MemoryTransport::new(received), CryptoSystem::from_key([1u8; 32], Role::Host));
+        match host.recv::<Response>() {
+            Err(ChannelError::UnexpectedPacket(Packet::Response(response))) => assert_eq!(response.status, ResponseStatus::Event),
+            other => panic!("unexpected result: {:?}", other.map(|r| r.status)),
+        }
+        assert_eq!(host.take_discarded().len(), 1);
+    }
+
+    #[test]
+    fn test_packets_in_other_codec_are_accepted() {
+        let mut device = SecureChannel::new(MemoryTransport::new(Vec::new()), CryptoSystem::from_key([1u8; 32], Role::Device));
+        device.send_packet(&Packet::Response(Response::event("ESP32 ready"))).unwrap();
+
+        let crypto = CryptoSystem::from_key([1u8; 32], Role::Host).with_codec(WireCodec::Cbor);
+        let mut host = SecureChannel::new(MemoryTransport::new(device.into_inner().tx), crypto);
+        assert!(matches!(host.recv_packet(), Ok(Packet::Response(_))));
+        assert!(host.take_discarded().is_empty());
+    }
+
+    #[test]
+    fn test_line_ending_bytes_survive_framing() {
+        let blob = b"line1\nline2\r\n\r\x0a\x0d".to_vec();
+        let command = Command::new("write_config").with_binary(blob.clone());
+
+        for codec in WireCodec::ALL {
+            let crypto = CryptoSystem::from_key([1u8; 32], Role::Host).with_codec(codec);
+            let mut host = SecureChannel::new(MemoryTransport::new(Vec::new()), crypto);
+            host.send_packet(&Packet::Command(command.clone())).unwrap();
+            host.send(&command).unwrap();
+
+            let sent = host.into_inner().tx;
+            if codec == WireCodec::Cbor {
+                // CBORではバイナリがそのままフレームに入る
+                assert!(sent.contains(&b'\n') && sent.contains(&b'\r'));
+            }
+
+            let crypto = CryptoSystem::from_key([1u8; 32], Role::Device).with_codec(codec);
+            let mut device = SecureChannel::new(MemoryTransport::new(sent), crypto);
+            match device.recv_packet().unwrap() {
+                Packet::Command(received) => assert_eq!(received.binary.as_deref(), Some(blob.as_slice())),
+                other => panic!("unexpected packet: {:?}", other),
+            }
+            let received: Command = device.recv().unwrap();
+            assert_eq!(received.binary.as_deref(), Some(blob.as_slice()));
+            assert!(device.take_discarded().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_oversized_packet_is_rejected_before_sending() {
+        let crypto = CryptoSystem::from_key([1u8; 32], Role::Host);
+        let mut host = SecureChannel::new(MemoryTransport::new(Vec::new()), crypto).with_max_frame_len(256);
+        let large = Command::new("write_config").with_binary(vec![0xAB; 512]);
+
+        assert!(matches!(host.send(&large), Err(ChannelError::FrameTooLarge { max: 256, .. })));
+        assert!(matches!(host.send_packet(&Packet::Command(large)), Err(ChannelError::FrameTooLarge { max: 256, .. })));
+        assert!(host.get_ref().tx.is_empty());
+
+        host.send(&ping()).unwrap();
+        assert!(!host.get_ref().tx.is_empty());
+    }
+}
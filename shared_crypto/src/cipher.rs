@@ -0,0 +1,127 @@
+//! # 暗号スイート
+//!
+//! AEAD暗号の選択肢と、ハンドシェイクでのネゴシエーションを提供します。
+//! AESアクセラレータのないターゲット（ESP32-C3など）ではChaCha20-Poly1305の方が高速で、
+//! XChaCha20-Poly1305は24バイトnonceのため大量のメッセージでもランダムnonceを安全に使えます。
+
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use serde::{Deserialize, Serialize};
+
+use crate::CryptoError;
+
+/// AEAD暗号スイート
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum CipherSuite {
+    /// AES-256-GCM（12バイトnonce）
+    #[default]
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305（12バイトnonce）
+    #[serde(rename = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    /// XChaCha20-Poly1305（24バイトnonce）
+    #[serde(rename = "xchacha20-poly1305")]
+    XChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    /// 実装されている全スイート
+    pub const ALL: [CipherSuite; 3] = [
+        CipherSuite::Aes256Gcm,
+        CipherSuite::ChaCha20Poly1305,
+        CipherSuite::XChaCha20Poly1305,
+    ];
+
+    /// 認証データに含める識別子
+    pub fn id(self) -> u8 {
+        match self {
+            CipherSuite::Aes256Gcm => 1,
+            CipherSuite::ChaCha20Poly1305 => 2,
+            CipherSuite::XChaCha20Poly1305 => 3,
+        }
+    }
+
+    /// nonceの長さ（バイト）
+    pub fn nonce_len(self) -> usize {
+        match self {
+            CipherSuite::Aes256Gcm | CipherSuite::ChaCha20Poly1305 => 12,
+            CipherSuite::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    /// 相手が提示したスイートから、自分の優先順で最初に一致するものを選択
+    ///
+    /// # 引数
+    /// - `preferred`: 自分がサポートするスイート（優先順）
+    /// - `offered`: 相手がサポートするスイート
+    pub fn negotiate(preferred: &[CipherSuite], offered: &[CipherSuite]) -> Option<CipherSuite> {
+        preferred.iter()
+            .copied()
+            .find(|suite| offered.contains(suite))
+    }
+
+    /// 暗号化（nonceの長さは呼び出し側で保証すること）
+    pub(crate) fn seal(self, key: &[u8; 32], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let payload = Payload { msg: plaintext, aad };
+        match self {
+            CipherSuite::Aes256Gcm => Aes256Gcm::new(key.into())
+                .encrypt(nonce.into(), payload),
+            CipherSuite::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+                .encrypt(nonce.into(), payload),
+            CipherSuite::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.into())
+                .encrypt(nonce.into(), payload),
+        }
+        .map_err(|_| CryptoError::EncryptionFailed)
+    }
+
+    /// 復号化と認証タグの検証
+    pub(crate) fn open(self, key: &[u8; 32], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if nonce.len() != self.nonce_len() {
+            return Err(CryptoError::DecryptionFailed);
+        }
+
+        let payload = Payload { msg: ciphertext, aad };
+        match self {
+            CipherSuite::Aes256Gcm => Aes256Gcm::new(key.into())
+                .decrypt(nonce.into(), payload),
+            CipherSuite::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+                .decrypt(nonce.into(), payload),
+            CipherSuite::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.into())
+                .decrypt(nonce.into(), payload),
+        }
+        .map_err(|_| CryptoError::DecryptionFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_suite_round_trips() {
+        let key = [5u8; 32];
+        for suite in CipherSuite::ALL {
+            let nonce = vec![1u8; suite.nonce_len()];
+            let ciphertext = suite.seal(&key, &nonce, b"aad", b"hello").unwrap();
+            assert_eq!(suite.open(&key, &nonce, b"aad", &ciphertext).unwrap(), b"hello");
+            assert!(suite.open(&key, &nonce, b"other", &ciphertext).is_err());
+        }
+    }
+
+    #[test]
+    fn test_serialized_names() {
+        assert_eq!(serde_json::to_string(&CipherSuite::Aes256Gcm).unwrap(), "\"aes-256-gcm\"");
+        assert_eq!(serde_json::to_string(&CipherSuite::XChaCha20Poly1305).unwrap(), "\"xchacha20-poly1305\"");
+    }
+
+    #[test]
+    fn test_negotiate_uses_own_preference() {
+        let device = [CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm];
+        let host = [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305];
+        assert_eq!(CipherSuite::negotiate(&device, &host), Some(CipherSuite::ChaCha20Poly1305));
+        assert_eq!(CipherSuite::negotiate(&device, &[CipherSuite::XChaCha20Poly1305]), None);
+    }
+}
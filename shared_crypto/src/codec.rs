@@ -0,0 +1,225 @@
+//! # ワイヤーコーデック
+//!
+//! メッセージ（コマンド・レスポンス・暗号化エンベロープ）をバイト列に変換する形式です。
+//!
+//! - [`WireCodec::Json`] ― 従来のJSON形式。バイナリはBase64文字列になります
+//! - [`WireCodec::Cbor`] ― コンパクトなバイナリ形式（CBOR）。バイナリはそのまま格納されるため、
+//!   Base64による約33%の膨張とJSONのキー・引用符のオーバーヘッドがなくなります
+//!
+//! バイナリ形式を改行区切りの回線で送る場合はフレーミングが必要です。
+//!
+//! セッションのコーデックはハンドシェイクでネゴシエーションされ、両端が対応していればCBORになります。
+//! ハンドシェイク前の通信と、コーデックを提示しない古い相手とのセッションはJSONです。
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::CryptoError;
+
+/// メッセージのシリアライズ形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireCodec {
+    /// JSON（バイナリはBase64）
+    #[default]
+    Json,
+    /// CBOR（RFC 8949）
+    Cbor,
+}
+
+impl WireCodec {
+    /// サポートするすべてのコーデック（推奨順）
+    pub const ALL: [WireCodec; 2] = [WireCodec::Cbor, WireCodec::Json];
+
+    /// ハンドシェイクのMAC計算に使う1バイトの識別子
+    pub fn id(self) -> u8 {
+        match self {
+            WireCodec::Json => 0,
+            WireCodec::Cbor => 1,
+        }
+    }
+
+    /// 相手が提示したコーデックから、自分の優先順で最初に一致するものを選択
+    ///
+    /// # 引数
+    /// - `preferred`: 自分がサポートするコーデック（優先順）
+    /// - `offered`: 相手がサポートするコーデック
+    pub fn negotiate(preferred: &[WireCodec], offered: &[WireCodec]) -> Option<WireCodec> {
+        preferred.iter()
+            .copied()
+            .find(|codec| offered.contains(codec))
+    }
+
+    /// 値をバイト列にシリアライズ
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, CryptoError> {
+        match self {
+            WireCodec::Json => serde_json::to_vec(value)
+                .map_err(|_| CryptoError::InvalidFormat),
+            WireCodec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|_| CryptoError::InvalidFormat)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// バイト列から値をデシリアライズ
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CryptoError> {
+        match self {
+            WireCodec::Json => serde_json::from_slice(bytes)
+                .map_err(|_| CryptoError::InvalidFormat),
+            WireCodec::Cbor => ciborium::from_reader(bytes)
+                .map_err(|_| CryptoError::InvalidFormat),
+        }
+    }
+}
+
+/// バイナリフィールド用のserdeヘルパー
+///
+/// 人間が読める形式（JSON）ではBase64文字列、バイナリ形式（CBOR）ではバイト列として表現します。
+/// デシリアライズ時はどちらの表現も受け付けます。
+pub(crate) mod base64_bytes {
+    use std::fmt;
+
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&BASE64.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_any(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a Base64 string or a byte string")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            BASE64.decode(value).map_err(E::custom)
+        }
+
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+            Ok(value.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(value)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+/// `Option<Vec<u8>>` 用の [`base64_bytes`]
+pub(crate) mod optional_base64_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    struct BytesRef<'a>(&'a [u8]);
+
+    impl Serialize for BytesRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::base64_bytes::serialize(self.0, serializer)
+        }
+    }
+
+    struct Bytes(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for Bytes {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::base64_bytes::deserialize(deserializer).map(Bytes)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&BytesRef(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<Bytes>::deserialize(deserializer)?.map(|bytes| bytes.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, CryptoSystem, EncryptedMessage, Role};
+
+    #[test]
+    fn test_round_trip_in_both_codecs() {
+        let command = Command::new("hello").with_data("data");
+        for codec in [WireCodec::Json, WireCodec::Cbor] {
+            let bytes = codec.encode(&command).unwrap();
+            let decoded: Command = codec.decode(&bytes).unwrap();
+            assert_eq!(decoded.action, "hello");
+            assert_eq!(decoded.data.as_deref(), Some("data"));
+        }
+    }
+
+    #[test]
+    fn test_cbor_envelope_is_smaller() {
+        let crypto = CryptoSystem::from_key([1u8; 32], Role::Device);
+        let encrypted = crypto.encrypt(&"x".repeat(200)).unwrap();
+
+        let json = WireCodec::Json.encode(&encrypted).unwrap();
+        let cbor = WireCodec::Cbor.encode(&encrypted).unwrap();
+        // Base64の33%増しがなくなる分だけ小さくなる
+        assert!(cbor.len() * 5 < json.len() * 4);
+
+        let decoded: EncryptedMessage = WireCodec::Cbor.decode(&cbor).unwrap();
+        assert_eq!(decoded.ciphertext, encrypted.ciphertext);
+        assert_eq!(decoded.nonce, encrypted.nonce);
+    }
+
+    #[test]
+    fn test_binary_payload_in_both_codecs() {
+        let blob: Vec<u8> = (0..=255).collect();
+        let command = Command::new("write_config").with_binary(blob.clone());
+
+        for codec in [WireCodec::Json, WireCodec::Cbor] {
+            let decoded: Command = codec.decode(&codec.encode(&command).unwrap()).unwrap();
+            assert_eq!(decoded.binary.as_deref(), Some(blob.as_slice()));
+        }
+
+        // CBORではBase64にせずそのまま格納される
+        assert!(WireCodec::Cbor.encode(&command).unwrap().len() < blob.len() + 64);
+        assert!(WireCodec::Json.encode(&command).unwrap().len() > blob.len() * 4 / 3);
+
+        let without: Command = WireCodec::Cbor.decode(&WireCodec::Cbor.encode(&Command::new("ping")).unwrap()).unwrap();
+        assert_eq!(without.binary, None);
+    }
+
+    #[test]
+    fn test_negotiation_follows_local_preference() {
+        assert_eq!(WireCodec::negotiate(&WireCodec::ALL, &[WireCodec::Json, WireCodec::Cbor]), Some(WireCodec::Cbor));
+        assert_eq!(WireCodec::negotiate(&[WireCodec::Json], &WireCodec::ALL), Some(WireCodec::Json));
+        assert_eq!(WireCodec::negotiate(&[WireCodec::Cbor], &[WireCodec::Json]), None);
+        assert_eq!(serde_json::to_string(&WireCodec::Cbor).unwrap(), "\"cbor\"");
+    }
+
+    #[test]
+    fn test_invalid_input_is_reported() {
+        assert!(matches!(WireCodec::Json.decode::<Command>(b"not json"), Err(CryptoError::InvalidFormat)));
+        assert!(matches!(WireCodec::Cbor.decode::<Command>(&[0xff]), Err(CryptoError::InvalidFormat)));
+    }
+}
@@ -0,0 +1,193 @@
+//! # フレーミング
+//!
+//! シリアル回線上でメッセージの境界を表すためのフレーム形式です。
+//!
+//! ```text
+//! 0x00 | COBS( ペイロード | CRC-32 (LE) ) | 0x00
+//! ```
+//!
+//! COBSによりフレーム内に `0x00` が現れないため、区切りバイトでいつでも再同期できます。
+//! 同じコンソールに出力されるESP-IDFのログや起動時のバナーは、
+//! CRCの検証に失敗した区間として [`FrameEvent::Discarded`] で通知されます。
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::CryptoError;
+
+/// フレームの区切りバイト
+pub const FRAME_DELIMITER: u8 = 0x00;
+
+/// デフォルトの最大ペイロード長（バイト）
+pub const DEFAULT_MAX_FRAME_LEN: usize = 4096;
+
+/// CRCトレーラーの長さ（バイト）
+const CRC_LEN: usize = 4;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// ペイロードをフレームにエンコード（前後に区切りバイトを付与）
+///
+/// 先頭の区切りバイトにより、直前に出力されたログの途中からでも受信側が同期できます。
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(payload.len() + CRC_LEN);
+    body.extend_from_slice(payload);
+    body.extend_from_slice(&CRC32.checksum(payload).to_le_bytes());
+
+    let mut frame = Vec::with_capacity(cobs::max_encoding_length(body.len()) + 2);
+    frame.push(FRAME_DELIMITER);
+    frame.extend_from_slice(&cobs::encode_vec(&body));
+    frame.push(FRAME_DELIMITER);
+    frame
+}
+
+/// 区切りバイトを除いたフレーム本体をデコードし、CRCを検証
+///
+/// # エラー
+/// COBSの形式が不正な場合やCRCが一致しない場合は `CryptoError::InvalidFrame`
+pub fn decode_frame(encoded: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut body = cobs::decode_vec(encoded).map_err(|_| CryptoError::InvalidFrame)?;
+    if body.len() < CRC_LEN {
+        return Err(CryptoError::InvalidFrame);
+    }
+
+    let crc_bytes = body.split_off(body.len() - CRC_LEN);
+    let expected = u32::from_le_bytes([crc_bytes[0], crc_bytes[1], crc_bytes[2], crc_bytes[3]]);
+    if CRC32.checksum(&body) != expected {
+        return Err(CryptoError::InvalidFrame);
+    }
+    Ok(body)
+}
+
+/// [`FrameDecoder`] が受信データから取り出した結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameEvent {
+    /// CRCの検証に成功したペイロード
+    Frame(Vec<u8>),
+    /// フレームとして解釈できなかったバイト列（ログ出力・起動バナー・破損データ）
+    Discarded(Vec<u8>),
+}
+
+/// 受信バイト列を少しずつ受け取ってフレームを取り出すデコーダ
+///
+/// 破損したフレームや最大長を超えたデータは破棄し、次の区切りバイトから再同期します。
+#[derive(Debug, Clone)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_len: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    /// デフォルトの最大ペイロード長でデコーダを作成
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    /// 最大ペイロード長を設定
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// 受信したバイト列を投入し、完成したフレームと破棄したデータを返す
+    ///
+    /// # 引数
+    /// * `data` - シリアルポートから読み取ったバイト列（任意の位置で分割されていてよい）
+    pub fn feed(&mut self, data: &[u8]) -> Vec<FrameEvent> {
+        let max_encoded_len = cobs::max_encoding_length(self.max_frame_len + CRC_LEN);
+        let mut events = Vec::new();
+
+        for &byte in data {
+            if byte == FRAME_DELIMITER {
+                if !self.buffer.is_empty() {
+                    let encoded = std::mem::take(&mut self.buffer);
+                    events.push(match decode_frame(&encoded) {
+                        Ok(payload) => FrameEvent::Frame(payload),
+                        Err(_) => FrameEvent::Discarded(encoded),
+                    });
+                }
+                continue;
+            }
+
+            if self.buffer.len() >= max_encoded_len {
+                // 区切りバイトが来ないまま上限を超えた場合は破棄して次の区切りを待つ
+                events.push(FrameEvent::Discarded(std::mem::take(&mut self.buffer)));
+            }
+            self.buffer.push(byte);
+        }
+
+        events
+    }
+
+    /// 次の区切りバイトを待っているデータを破棄（再接続時など）
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(events: Vec<FrameEvent>) -> Vec<Vec<u8>> {
+        events.into_iter()
+            .filter_map(|event| match event {
+                FrameEvent::Frame(payload) => Some(payload),
+                FrameEvent::Discarded(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_with_split_input() {
+        let payload = b"binary\x00payload\x00with zeros".to_vec();
+        let encoded = encode_frame(&payload);
+        assert_eq!(encoded.iter().filter(|&&b| b == FRAME_DELIMITER).count(), 2);
+
+        let mut decoder = FrameDecoder::new();
+        let mut received = Vec::new();
+        for chunk in encoded.chunks(3) {
+            received.extend(frames(decoder.feed(chunk)));
+        }
+        assert_eq!(received, vec![payload]);
+    }
+
+    #[test]
+    fn test_resync_after_log_lines_and_corruption() {
+        let mut stream = b"ESP-ROM:esp32s3-20210327\r\nI (42) boot: ESP-IDF v5.1\r\n".to_vec();
+        stream.extend(encode_frame(b"first"));
+
+        let mut corrupted = encode_frame(b"second");
+        corrupted[3] ^= 0x40;
+        stream.extend(corrupted);
+
+        // フレームの途中に起動バナーが割り込んだ場合
+        let interrupted = encode_frame(b"third");
+        stream.extend(&interrupted[..4]);
+        stream.extend(b"\r\nrst:0x1 (POWERON),boot:0x8\r\n");
+        stream.extend(encode_frame(b"fourth"));
+
+        let events = FrameDecoder::new().feed(&stream);
+        let discarded = events.iter().filter(|e| matches!(e, FrameEvent::Discarded(_))).count();
+        assert_eq!(frames(events), vec![b"first".to_vec(), b"fourth".to_vec()]);
+        assert_eq!(discarded, 3);
+    }
+
+    #[test]
+    fn test_oversized_data_is_discarded() {
+        let mut decoder = FrameDecoder::new().with_max_frame_len(8);
+        let events = decoder.feed(&[b'x'; 64]);
+        assert!(events.iter().all(|e| matches!(e, FrameEvent::Discarded(_))));
+
+        assert_eq!(frames(decoder.feed(&encode_frame(b"ok"))), vec![b"ok".to_vec()]);
+        assert!(matches!(decode_frame(&[0x01]), Err(CryptoError::InvalidFrame)));
+    }
+}
@@ -0,0 +1,482 @@
+//! # エフェメラル鍵交換ハンドシェイク
+//!
+//! X25519のエフェメラル鍵交換を事前共有鍵（PSK）で認証し、
+//! セッションごとの `CryptoSystem` を確立します。
+//! セッション鍵は接続ごとに使い捨てのため、PSKが漏洩しても
+//! 過去に記録された通信は復号できません（前方秘匿性）。
+//!
+//! ## 手順
+//! 1. ホスト（Tauri）: [`Handshake::initiate`] でHello（対応する暗号スイートとコーデックの一覧を含む）を作成して送信
+//! 2. デバイス（ESP32）: [`Handshake::respond`] でHelloの鍵IDが示すPSKを鍵リングから選び、
+//!    暗号スイートとコーデックを選択した応答を送信してセッションを確立
+//! 3. ホスト: [`Handshake::finish`] で応答を検証し、セッションを確立
+//!
+//! Helloと応答のMACは、ラベルに続けて各フィールドを「4バイトの長さ（ビッグエンディアン）|| 内容」の形で連結した入力に対して計算します。
+//! 長さで区切るため、可変長の一覧（暗号スイート・コーデック）と鍵IDの境界をずらした書き換えは検出されます。
+//!
+//! デバイスの鍵リングに新旧のPSKを登録しておけば、ホストを順に新しいPSKへ切り替えて
+//! PSKをローテーションできます（[`Keyring`] の手順を参照）。
+//!
+//! ```rust
+//! use esp32_tauri_crypto::{create_default_crypto, handshake::Handshake, CipherSuite, Keyring, Role, WireCodec};
+//!
+//! let host_psk = create_default_crypto(Role::Host);
+//! let device_psks = Keyring::new(create_default_crypto(Role::Device));
+//!
+//! let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+//! let (reply, device_session) = Handshake::respond(&device_psks, &hello, &[CipherSuite::ChaCha20Poly1305], &WireCodec::ALL)?;
+//! let host_session = pending.finish(&reply)?;
+//! assert_eq!(host_session.cipher_suite(), CipherSuite::ChaCha20Poly1305);
+//! assert_eq!(host_session.codec(), WireCodec::Cbor);
+//!
+//! let encrypted = host_session.encrypt("hello")?;
+//! assert_eq!(device_session.decrypt(&encrypted)?, "hello");
+//! # Ok::<(), esp32_tauri_crypto::CryptoError>(())
+//! ```
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::{CipherSuite, CryptoError, CryptoSystem, Keyring, Role, WireCodec};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// ホストHelloのMACラベル
+const HOST_LABEL: &[u8] = b"esp32-tauri handshake host";
+/// デバイス応答のMACラベル
+const DEVICE_LABEL: &[u8] = b"esp32-tauri handshake device";
+/// PSKからMAC鍵を導出する際のinfo
+const MAC_KEY_INFO: &[u8] = b"esp32-tauri handshake mac key";
+/// セッション鍵導出のinfo
+const SESSION_INFO: &[u8] = b"esp32-tauri session key v1";
+
+/// ハンドシェイクで送受信するメッセージ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeMessage {
+    /// Base64エンコードされたX25519エフェメラル公開鍵
+    pub public_key: String,
+    /// 暗号スイート（Helloでは対応一覧、応答では選択した1つ）
+    pub suites: Vec<CipherSuite>,
+    /// 認証に使うPSKの鍵ID（応答ではHelloの値を返す）
+    #[serde(default)]
+    pub key_id: u32,
+    /// セッションのコーデック（Helloでは対応一覧、応答では選択した1つ。空ならJSON）
+    #[serde(default)]
+    pub codecs: Vec<WireCodec>,
+    /// Base64エンコードされたHMAC-SHA256（PSK由来の鍵による認証タグ）
+    pub mac: String,
+}
+
+/// ホスト側の進行中ハンドシェイク
+pub struct Handshake {
+    /// PSKから導出したMAC鍵
+    mac_key: [u8; 32],
+    /// PSK本体（セッション鍵導出のソルト）
+    psk: [u8; 32],
+    /// PSKの鍵ID
+    key_id: u32,
+    /// 自分のエフェメラル秘密鍵
+    secret: EphemeralSecret,
+    /// 自分のエフェメラル公開鍵
+    public: PublicKey,
+    /// Helloで提示した暗号スイート
+    offered: Vec<CipherSuite>,
+    /// Helloで提示したコーデック
+    offered_codecs: Vec<WireCodec>,
+}
+
+impl Handshake {
+    /// ホスト側：ハンドシェイクを開始
+    ///
+    /// # 引数
+    /// - `psk`: 事前共有鍵（鍵IDはHelloでデバイスに通知される）
+    /// - `suites`: ホストが対応する暗号スイート
+    /// - `codecs`: ホストが対応するコーデック
+    ///
+    /// # 戻り値
+    /// 進行中のハンドシェイクと、デバイスへ送信するHello
+    pub fn initiate(psk: &CryptoSystem, suites: &[CipherSuite], codecs: &[WireCodec]) -> (Self, HandshakeMessage) {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        let mac_key = derive_mac_key(&psk.key);
+
+        let key_id = psk.key_id();
+        let suite_ids = encode_suites(suites);
+        let codec_ids = encode_codecs(codecs);
+        let mac = compute_mac(&mac_key, HOST_LABEL, &[public.as_bytes(), &suite_ids, &key_id.to_be_bytes(), &codec_ids]);
+        let hello = HandshakeMessage {
+            public_key: BASE64.encode(public.as_bytes()),
+            suites: suites.to_vec(),
+            key_id,
+            codecs: codecs.to_vec(),
+            mac: BASE64.encode(mac),
+        };
+
+        let handshake = Self {
+            mac_key,
+            psk: psk.key,
+            key_id,
+            secret,
+            public,
+            offered: suites.to_vec(),
+            offered_codecs: codecs.to_vec(),
+        };
+        (handshake, hello)
+    }
+
+    /// デバイス側：ホストのHelloに応答してセッションを確立
+    ///
+    /// # 引数
+    /// - `psks`: 事前共有鍵の鍵リング（Helloの鍵IDで選択）
+    /// - `hello`: ホストから受信したHello
+    /// - `supported`: デバイスが対応する暗号スイート（優先順）
+    /// - `codecs`: デバイスが対応するコーデック（優先順）
+    ///
+    /// # 戻り値
+    /// ホストへ返す応答と、確立したセッション用の暗号化システム
+    ///
+    /// Helloにコーデックがない（古いホスト）場合、セッションはJSONになります。
+    ///
+    /// # エラー
+    /// 鍵リングにない鍵IDの場合は `UnknownKeyId`、認証に失敗した場合は `HandshakeFailed`
+    pub fn respond(
+        psks: &Keyring,
+        hello: &HandshakeMessage,
+        supported: &[CipherSuite],
+        codecs: &[WireCodec],
+    ) -> Result<(HandshakeMessage, CryptoSystem), CryptoError> {
+        let psk = psks.get(hello.key_id)
+            .ok_or(CryptoError::UnknownKeyId(hello.key_id))?;
+        let mac_key = derive_mac_key(&psk.key);
+        let host_public = decode_public_key(&hello.public_key)?;
+        verify_mac(
+            &mac_key,
+            HOST_LABEL,
+            &[host_public.as_bytes(), &encode_suites(&hello.suites), &hello.key_id.to_be_bytes(), &encode_codecs(&hello.codecs)],
+            &hello.mac,
+        )?;
+
+        let suite = CipherSuite::negotiate(supported, &hello.suites)
+            .ok_or(CryptoError::UnsupportedCipherSuite)?;
+        let selected_codecs = if hello.codecs.is_empty() {
+            Vec::new()
+        } else {
+            vec![WireCodec::negotiate(codecs, &hello.codecs).ok_or(CryptoError::HandshakeFailed)?]
+        };
+
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+
+        let mac = compute_mac(
+            &mac_key,
+            DEVICE_LABEL,
+            &[host_public.as_bytes(), public.as_bytes(), &[suite.id()], &encode_codecs(&selected_codecs)],
+        );
+        let codec = selected_codecs.first().copied().unwrap_or(WireCodec::Json);
+        let reply = HandshakeMessage {
+            public_key: BASE64.encode(public.as_bytes()),
+            suites: vec![suite],
+            key_id: hello.key_id,
+            codecs: selected_codecs,
+            mac: BASE64.encode(mac),
+        };
+
+        let session = derive_session(&psk.key, secret, &host_public, &host_public, &public, Role::Device)?
+            .with_cipher_suite(suite)
+            .with_key_id(hello.key_id)
+            .with_codec(codec);
+        Ok((reply, session))
+    }
+
+    /// 応答がこのハンドシェイクのHelloに対するものか（MACだけを検証する）
+    ///
+    /// ハンドシェイクをやり直した後に届いた、以前のHelloへの応答を読み飛ばすために使います。
+    pub fn is_reply_to(&self, reply: &HandshakeMessage) -> bool {
+        self.verify_reply(reply).is_ok()
+    }
+
+    /// ホスト側：デバイスの応答を検証してセッションを確立
+    pub fn finish(self, reply: &HandshakeMessage) -> Result<CryptoSystem, CryptoError> {
+        let (device_public, suite) = self.verify_reply(reply)?;
+
+        if !self.offered.contains(&suite) {
+            return Err(CryptoError::UnsupportedCipherSuite);
+        }
+        // コーデックを返さないのは古いデバイスで、JSONのまま
+        let codec = match reply.codecs.as_slice() {
+            [] => WireCodec::Json,
+            [codec] if self.offered_codecs.contains(codec) => *codec,
+            _ => return Err(CryptoError::HandshakeFailed),
+        };
+
+        Ok(derive_session(&self.psk, self.secret, &device_public, &self.public, &device_public, Role::Host)?
+            .with_cipher_suite(suite)
+            .with_key_id(self.key_id)
+            .with_codec(codec))
+    }
+
+    /// 応答のMACを検証し、デバイスの公開鍵と選択された暗号スイートを返す
+    fn verify_reply(&self, reply: &HandshakeMessage) -> Result<(PublicKey, CipherSuite), CryptoError> {
+        let device_public = decode_public_key(&reply.public_key)?;
+        let suite = match reply.suites.as_slice() {
+            [suite] => *suite,
+            _ => return Err(CryptoError::HandshakeFailed),
+        };
+        verify_mac(
+            &self.mac_key,
+            DEVICE_LABEL,
+            &[self.public.as_bytes(), device_public.as_bytes(), &[suite.id()], &encode_codecs(&reply.codecs)],
+            &reply.mac,
+        )?;
+        Ok((device_public, suite))
+    }
+}
+
+/// 暗号スイート一覧をMAC計算用のバイト列に変換
+fn encode_suites(suites: &[CipherSuite]) -> Vec<u8> {
+    suites.iter().map(|suite| suite.id()).collect()
+}
+
+/// コーデック一覧をMAC計算用のバイト列に変換
+fn encode_codecs(codecs: &[WireCodec]) -> Vec<u8> {
+    codecs.iter().map(|codec| codec.id()).collect()
+}
+
+/// PSKからハンドシェイク認証用のMAC鍵を導出
+fn derive_mac_key(psk: &[u8; 32]) -> [u8; 32] {
+    let mut mac_key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, psk)
+        .expand(MAC_KEY_INFO, &mut mac_key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    mac_key
+}
+
+/// ラベルと、長さで区切った各フィールドを入力したMAC
+fn transcript_mac(mac_key: &[u8; 32], label: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(mac_key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(label);
+    for part in parts {
+        let len = u32::try_from(part.len()).expect("handshake fields are shorter than 4 GiB");
+        mac.update(&len.to_be_bytes());
+        mac.update(part);
+    }
+    mac
+}
+
+fn compute_mac(mac_key: &[u8; 32], label: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    transcript_mac(mac_key, label, parts).finalize().into_bytes().into()
+}
+
+fn verify_mac(mac_key: &[u8; 32], label: &[u8], parts: &[&[u8]], encoded: &str) -> Result<(), CryptoError> {
+    let tag = BASE64.decode(encoded)
+        .map_err(|_| CryptoError::Base64DecodeFailed)?;
+
+    transcript_mac(mac_key, label, parts)
+        .verify_slice(&tag)
+        .map_err(|_| CryptoError::HandshakeFailed)
+}
+
+fn decode_public_key(encoded: &str) -> Result<PublicKey, CryptoError> {
+    let bytes: [u8; 32] = BASE64.decode(encoded)
+        .map_err(|_| CryptoError::Base64DecodeFailed)?
+        .try_into()
+        .map_err(|_| CryptoError::HandshakeFailed)?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// 共有秘密とトランスクリプト（ホスト公開鍵 || デバイス公開鍵）からセッション鍵を導出
+fn derive_session(
+    psk: &[u8; 32],
+    secret: EphemeralSecret,
+    peer_public: &PublicKey,
+    host_public: &PublicKey,
+    device_public: &PublicKey,
+    role: Role,
+) -> Result<CryptoSystem, CryptoError> {
+    let shared = secret.diffie_hellman(peer_public);
+    if !shared.was_contributory() {
+        return Err(CryptoError::HandshakeFailed);
+    }
+
+    let mut info = Vec::with_capacity(SESSION_INFO.len() + 64);
+    info.extend_from_slice(SESSION_INFO);
+    info.extend_from_slice(host_public.as_bytes());
+    info.extend_from_slice(device_public.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(psk), shared.as_bytes())
+        .expand(&info, &mut key)
+        .map_err(|_| CryptoError::KeyCreationFailed)?;
+
+    Ok(CryptoSystem::from_session_key(key, role))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_establishes_shared_session() {
+        let host_psk = CryptoSystem::from_key([7u8; 32], Role::Host);
+        let device_psk = CryptoSystem::from_key([7u8; 32], Role::Device);
+
+        let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let (reply, device_session) = Handshake::respond(&Keyring::new(device_psk.clone()), &hello, &[CipherSuite::XChaCha20Poly1305], &WireCodec::ALL).unwrap();
+        let host_session = pending.finish(&reply).unwrap();
+        assert_eq!(host_session.cipher_suite(), CipherSuite::XChaCha20Poly1305);
+        assert_eq!(device_session.cipher_suite(), CipherSuite::XChaCha20Poly1305);
+        assert_eq!(host_session.role(), Role::Host);
+        assert_eq!(device_session.role(), Role::Device);
+
+        let encrypted = host_session.encrypt("ping").unwrap();
+        assert_eq!(device_session.decrypt(&encrypted).unwrap(), "ping");
+
+        // セッション鍵はPSKそのものではない
+        assert!(device_psk.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_handshake_rejects_wrong_psk() {
+        let host_psk = CryptoSystem::from_key([1u8; 32], Role::Host);
+        let device_psks = Keyring::new(CryptoSystem::from_key([2u8; 32], Role::Device));
+
+        let (_, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        assert!(matches!(
+            Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL),
+            Err(CryptoError::HandshakeFailed)
+        ));
+    }
+
+    #[test]
+    fn test_handshake_rejects_tampered_suite_offer() {
+        let host_psk = CryptoSystem::from_key([4u8; 32], Role::Host);
+        let device_psk = Keyring::new(CryptoSystem::from_key([4u8; 32], Role::Device));
+
+        let (_, mut hello) = Handshake::initiate(&host_psk, &[CipherSuite::XChaCha20Poly1305], &WireCodec::ALL);
+        hello.suites = vec![CipherSuite::Aes256Gcm];
+        assert!(Handshake::respond(&device_psk, &hello, &CipherSuite::ALL, &WireCodec::ALL).is_err());
+
+        let (_, hello) = Handshake::initiate(&host_psk, &[CipherSuite::XChaCha20Poly1305], &WireCodec::ALL);
+        assert!(matches!(
+            Handshake::respond(&device_psk, &hello, &[CipherSuite::Aes256Gcm], &WireCodec::ALL),
+            Err(CryptoError::UnsupportedCipherSuite)
+        ));
+    }
+
+    #[test]
+    fn test_handshake_rejects_reply_for_other_hello() {
+        let host_psk = CryptoSystem::from_key([3u8; 32], Role::Host);
+        let device_psk = Keyring::new(CryptoSystem::from_key([3u8; 32], Role::Device));
+
+        let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let (_, other_hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let (stale_reply, _) = Handshake::respond(&device_psk, &other_hello, &CipherSuite::ALL, &WireCodec::ALL).unwrap();
+        let (reply, _) = Handshake::respond(&device_psk, &hello, &CipherSuite::ALL, &WireCodec::ALL).unwrap();
+
+        assert!(!pending.is_reply_to(&stale_reply));
+        assert!(pending.is_reply_to(&reply));
+        assert!(matches!(pending.finish(&stale_reply), Err(CryptoError::HandshakeFailed)));
+    }
+
+    #[test]
+    fn test_hello_fields_cannot_be_reframed() {
+        let host_psk = CryptoSystem::from_key([6u8; 32], Role::Host);
+        let mut device_psks = Keyring::new(CryptoSystem::from_key([6u8; 32], Role::Device));
+        // 書き換え後の鍵IDに同じPSKが登録されていても、MACで検出する
+        device_psks.insert(CryptoSystem::from_key([6u8; 32], Role::Device).with_key_id(0x0100_0000));
+
+        let suites = [CipherSuite::XChaCha20Poly1305, CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm];
+        let (_, mut hello) = Handshake::initiate(&host_psk, &suites, &[WireCodec::Json]);
+        // 暗号スイートの末尾（Aes256Gcm = 1）を鍵IDの先頭バイトに、鍵IDの末尾（0）をコーデック（Json = 0）にずらす
+        // （長さで区切らなければ、連結したバイト列は書き換え前と同じになる）
+        hello.suites.pop();
+        hello.key_id = 0x0100_0000;
+        hello.codecs.insert(0, WireCodec::Json);
+
+        let result = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL);
+        assert!(matches!(result, Err(CryptoError::HandshakeFailed)));
+    }
+
+    #[test]
+    fn test_codecs_cannot_be_added_to_legacy_hello() {
+        let host_psk = CryptoSystem::from_key([6u8; 32], Role::Host);
+        let mut device_psks = Keyring::new(CryptoSystem::from_key([6u8; 32], Role::Device));
+        device_psks.insert(CryptoSystem::from_key([6u8; 32], Role::Device).with_key_id(0x0100_0000));
+
+        // コーデックを提示しない古いホストのHelloに、鍵IDの末尾（0）をコーデック（Json = 0）として付け加える
+        let suites = [CipherSuite::XChaCha20Poly1305, CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm];
+        let (_, mut hello) = Handshake::initiate(&host_psk, &suites, &[]);
+        hello.suites.pop();
+        hello.key_id = 0x0100_0000;
+        hello.codecs = vec![WireCodec::Json];
+
+        let result = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL);
+        assert!(matches!(result, Err(CryptoError::HandshakeFailed)));
+    }
+
+    #[test]
+    fn test_responder_selects_psk_by_key_id() {
+        let mut device_psks = Keyring::new(CryptoSystem::from_key([1u8; 32], Role::Device).with_key_id(1));
+        device_psks.insert(CryptoSystem::from_key([2u8; 32], Role::Device).with_key_id(2));
+
+        // ローテーション中は旧PSKのホストも新PSKのホストも接続できる
+        for (key, key_id) in [([1u8; 32], 1), ([2u8; 32], 2)] {
+            let host_psk = CryptoSystem::from_key(key, Role::Host).with_key_id(key_id);
+            let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+            assert_eq!(hello.key_id, key_id);
+
+            let (reply, device_session) = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL).unwrap();
+            let host_session = pending.finish(&reply).unwrap();
+            assert_eq!(device_session.decrypt(&host_session.encrypt("ping").unwrap()).unwrap(), "ping");
+        }
+
+        let retired = CryptoSystem::from_key([9u8; 32], Role::Host).with_key_id(9);
+        let (_, hello) = Handshake::initiate(&retired, &CipherSuite::ALL, &WireCodec::ALL);
+        assert!(matches!(Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL), Err(CryptoError::UnknownKeyId(9))));
+
+        // 鍵IDはMACで認証される
+        let host_psk = CryptoSystem::from_key([1u8; 32], Role::Host).with_key_id(1);
+        let (_, mut hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        hello.key_id = 2;
+        assert!(matches!(Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL), Err(CryptoError::HandshakeFailed)));
+    }
+
+    #[test]
+    fn test_handshake_negotiates_codec() {
+        let host_psk = CryptoSystem::from_key([5u8; 32], Role::Host);
+        let device_psks = Keyring::new(CryptoSystem::from_key([5u8; 32], Role::Device));
+
+        // 両端が対応していればCBOR
+        let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let (reply, device_session) = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL).unwrap();
+        assert_eq!(reply.codecs, vec![WireCodec::Cbor]);
+        assert_eq!(device_session.codec(), WireCodec::Cbor);
+        assert_eq!(pending.finish(&reply).unwrap().codec(), WireCodec::Cbor);
+
+        // デバイスがJSONしか使わない場合
+        let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &WireCodec::ALL);
+        let (reply, _) = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &[WireCodec::Json]).unwrap();
+        assert_eq!(pending.finish(&reply).unwrap().codec(), WireCodec::Json);
+
+        // コーデックを提示しない古いホストとはJSON
+        let (pending, hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &[]);
+        let (reply, device_session) = Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL).unwrap();
+        assert!(reply.codecs.is_empty());
+        assert_eq!(device_session.codec(), WireCodec::Json);
+        assert_eq!(pending.finish(&reply).unwrap().codec(), WireCodec::Json);
+
+        // 提示したコーデックの改ざんは検出される
+        let (_, mut hello) = Handshake::initiate(&host_psk, &CipherSuite::ALL, &[WireCodec::Json]);
+        hello.codecs = vec![WireCodec::Cbor];
+        assert!(matches!(
+            Handshake::respond(&device_psks, &hello, &CipherSuite::ALL, &WireCodec::ALL),
+            Err(CryptoError::HandshakeFailed)
+        ));
+    }
+}
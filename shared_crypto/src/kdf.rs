@@ -0,0 +1,115 @@
+//! # パスフレーズからの鍵導出
+//!
+//! PBKDF2-HMAC-SHA256 でパスフレーズから32バイト鍵を導出します。
+//! ソルトと反復回数は [`KdfParams`] にまとめて保持し、
+//! 両端が同じパラメータを共有することで同一の鍵を導出できます。
+//!
+//! ソルトはデプロイメントごとに [`KdfParams::generate`] で生成してください。
+//! 全デバイスで同じソルトを使うと、1つの事前計算表ですべてのデバイスのパスフレーズを探索できます。
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use pbkdf2::pbkdf2_hmac;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+
+use crate::CryptoError;
+
+/// ソルト長（バイト）
+pub const SALT_LEN: usize = 16;
+
+/// 推奨の反復回数（ESP32-S3で約10秒かかるため、起動時に一度だけ導出する）
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// 許容する最小の反復回数（これ未満のパラメータでは鍵を導出しない）
+pub const MIN_ITERATIONS: u32 = 50_000;
+
+/// デモ・シミュレータ用の固定パラメータ（[`crate::create_default_crypto`] が使用）
+///
+/// ソルトが公開されているため、製品の鍵の導出には使わないでください。
+pub(crate) const DEMO_PARAMS: KdfParams = KdfParams {
+    salt: *b"esp32-tauri-kdf1",
+    iterations: MIN_ITERATIONS,
+};
+
+/// 鍵導出パラメータ
+///
+/// ソルトと反復回数を鍵と一緒に保存・共有するための構造体です。
+/// ソルトを省略したデフォルト値はないため、[`KdfParams::generate`] で生成するか、
+/// 保存したパラメータを読み込んで使用してください。
+/// JSONではソルトをBase64文字列として表現します。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// ソルト
+    #[serde(serialize_with = "serialize_salt", deserialize_with = "deserialize_salt")]
+    pub salt: [u8; SALT_LEN],
+    /// PBKDF2の反復回数
+    pub iterations: u32,
+}
+
+impl KdfParams {
+    /// ランダムなソルトで新しいパラメータを生成
+    ///
+    /// デプロイメント（またはデバイス）のプロビジョニング時に生成し、鍵設定と一緒に両端へ配布してください。
+    /// 反復回数は通常 [`DEFAULT_ITERATIONS`] を指定します。
+    pub fn generate(iterations: u32) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self { salt, iterations }
+    }
+
+    /// パスフレーズから32バイト鍵を導出
+    ///
+    /// # エラー
+    /// 反復回数が [`MIN_ITERATIONS`] 未満の場合は `KeyCreationFailed`
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; 32], CryptoError> {
+        if self.iterations < MIN_ITERATIONS {
+            return Err(CryptoError::KeyCreationFailed);
+        }
+
+        let mut key = [0u8; 32];
+        pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &self.salt, self.iterations, &mut key);
+        Ok(key)
+    }
+}
+
+fn serialize_salt<S: Serializer>(salt: &[u8; SALT_LEN], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64.encode(salt))
+}
+
+fn deserialize_salt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; SALT_LEN], D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    let bytes = BASE64.decode(encoded).map_err(serde::de::Error::custom)?;
+    bytes.try_into()
+        .map_err(|_| serde::de::Error::custom("invalid salt length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_params_derive_same_key() {
+        let params = KdfParams::generate(MIN_ITERATIONS);
+        let a = params.derive_key("passphrase").unwrap();
+        let b = params.derive_key("passphrase").unwrap();
+        assert_eq!(a, b);
+
+        let other = KdfParams::generate(MIN_ITERATIONS);
+        assert_ne!(a, other.derive_key("passphrase").unwrap());
+    }
+
+    #[test]
+    fn test_rejects_low_iteration_count() {
+        let params = KdfParams { salt: [0u8; SALT_LEN], iterations: MIN_ITERATIONS - 1 };
+        assert!(params.derive_key("passphrase").is_err());
+    }
+
+    #[test]
+    fn test_params_json_round_trip() {
+        let params = KdfParams::generate(DEFAULT_ITERATIONS);
+        let json = serde_json::to_string(&params).unwrap();
+        let decoded: KdfParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(params, decoded);
+    }
+}
@@ -0,0 +1,164 @@
+//! # 鍵リング
+//!
+//! 複数の鍵を鍵IDで管理し、鍵のローテーションを段階的に行うための型です。
+//!
+//! ## ローテーション手順
+//! 1. 全デバイスの鍵リングに新しい鍵を追加（旧鍵はアクティブのまま）
+//! 2. ホストとデバイスのアクティブ鍵を新しい鍵に切り替え
+//! 3. 全台の切り替えが終わったら旧鍵を削除
+//!
+//! 移行期間中は、どちらの鍵で暗号化されたメッセージも復号化できます。
+
+use std::collections::BTreeMap;
+
+use crate::{Command, CryptoError, CryptoSystem, EncryptedMessage, Response};
+
+/// 鍵IDで選択される暗号化システムの集合
+///
+/// 暗号化はアクティブな鍵で行い、復号化はメッセージの鍵IDが示す鍵で行います。
+#[derive(Clone)]
+pub struct Keyring {
+    /// 鍵IDごとの暗号化システム
+    keys: BTreeMap<u32, CryptoSystem>,
+    /// 暗号化に使用する鍵のID
+    active: u32,
+}
+
+impl Keyring {
+    /// アクティブな鍵を1つ持つ鍵リングを作成
+    pub fn new(active: CryptoSystem) -> Self {
+        let active_id = active.key_id();
+        let mut keys = BTreeMap::new();
+        keys.insert(active_id, active);
+        Self { keys, active: active_id }
+    }
+
+    /// 鍵を追加（同じ鍵IDの既存の鍵は置き換えて返す）
+    pub fn insert(&mut self, crypto: CryptoSystem) -> Option<CryptoSystem> {
+        self.keys.insert(crypto.key_id(), crypto)
+    }
+
+    /// 暗号化に使用する鍵を切り替え
+    ///
+    /// # エラー
+    /// 鍵リングにない鍵IDの場合は `UnknownKeyId`
+    pub fn set_active(&mut self, key_id: u32) -> Result<(), CryptoError> {
+        if !self.keys.contains_key(&key_id) {
+            return Err(CryptoError::UnknownKeyId(key_id));
+        }
+        self.active = key_id;
+        Ok(())
+    }
+
+    /// 鍵を削除
+    ///
+    /// アクティブな鍵は削除できません（`None` を返します）。
+    pub fn remove(&mut self, key_id: u32) -> Option<CryptoSystem> {
+        if key_id == self.active {
+            return None;
+        }
+        self.keys.remove(&key_id)
+    }
+
+    /// アクティブな鍵
+    pub fn active(&self) -> &CryptoSystem {
+        &self.keys[&self.active]
+    }
+
+    /// 鍵IDに対応する鍵
+    pub fn get(&self, key_id: u32) -> Option<&CryptoSystem> {
+        self.keys.get(&key_id)
+    }
+
+    /// 登録されている鍵IDの一覧（昇順）
+    pub fn key_ids(&self) -> Vec<u32> {
+        self.keys.keys().copied().collect()
+    }
+
+    /// メッセージの鍵IDに対応する鍵を取得
+    fn key_for(&self, encrypted: &EncryptedMessage) -> Result<&CryptoSystem, CryptoError> {
+        self.get(encrypted.key_id)
+            .ok_or(CryptoError::UnknownKeyId(encrypted.key_id))
+    }
+
+    /// アクティブな鍵で文字列を暗号化
+    pub fn encrypt(&self, plaintext: &str) -> Result<EncryptedMessage, CryptoError> {
+        self.active().encrypt(plaintext)
+    }
+
+    /// 鍵IDが示す鍵で復号化
+    pub fn decrypt(&self, encrypted: &EncryptedMessage) -> Result<String, CryptoError> {
+        self.key_for(encrypted)?.decrypt(encrypted)
+    }
+
+    /// アクティブな鍵でコマンドを暗号化
+    pub fn encrypt_command(&self, command: &Command) -> Result<EncryptedMessage, CryptoError> {
+        self.active().encrypt_command(command)
+    }
+
+    /// アクティブな鍵でレスポンスを暗号化
+    pub fn encrypt_response(&self, response: &Response) -> Result<EncryptedMessage, CryptoError> {
+        self.active().encrypt_response(response)
+    }
+
+    /// 鍵IDが示す鍵でコマンドを復号化
+    pub fn decrypt_to_command(&self, encrypted: &EncryptedMessage) -> Result<Command, CryptoError> {
+        self.key_for(encrypted)?.decrypt_to_command(encrypted)
+    }
+
+    /// 鍵IDが示す鍵でレスポンスを復号化
+    pub fn decrypt_to_response(&self, encrypted: &EncryptedMessage) -> Result<Response, CryptoError> {
+        self.key_for(encrypted)?.decrypt_to_response(encrypted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+
+    fn ping() -> Command {
+        Command::new("ping")
+    }
+
+    #[test]
+    fn test_decrypts_with_key_named_by_message() {
+        let old_host = CryptoSystem::from_key([1u8; 32], Role::Host).with_key_id(1);
+        let new_host = CryptoSystem::from_key([2u8; 32], Role::Host).with_key_id(2);
+
+        let mut device = Keyring::new(CryptoSystem::from_key([1u8; 32], Role::Device).with_key_id(1));
+        device.insert(CryptoSystem::from_key([2u8; 32], Role::Device).with_key_id(2));
+
+        let encrypted = old_host.encrypt_command(&ping()).unwrap();
+        assert_eq!(encrypted.key_id, 1);
+        assert_eq!(device.decrypt_to_command(&encrypted).unwrap().action, "ping");
+
+        let encrypted = new_host.encrypt_command(&ping()).unwrap();
+        assert_eq!(encrypted.key_id, 2);
+        assert_eq!(device.decrypt_to_command(&encrypted).unwrap().action, "ping");
+    }
+
+    #[test]
+    fn test_encrypts_with_active_key() {
+        let mut host = Keyring::new(CryptoSystem::from_key([1u8; 32], Role::Host).with_key_id(1));
+        host.insert(CryptoSystem::from_key([2u8; 32], Role::Host).with_key_id(2));
+
+        assert_eq!(host.encrypt_command(&ping()).unwrap().key_id, 1);
+        host.set_active(2).unwrap();
+        assert_eq!(host.encrypt_command(&ping()).unwrap().key_id, 2);
+
+        assert!(matches!(host.set_active(3), Err(CryptoError::UnknownKeyId(3))));
+        assert!(host.remove(2).is_none());
+        assert!(host.remove(1).is_some());
+        assert_eq!(host.key_ids(), vec![2]);
+    }
+
+    #[test]
+    fn test_unknown_key_id_is_reported() {
+        let host = CryptoSystem::from_key([9u8; 32], Role::Host).with_key_id(9);
+        let device = Keyring::new(CryptoSystem::from_key([1u8; 32], Role::Device).with_key_id(1));
+
+        let encrypted = host.encrypt_command(&ping()).unwrap();
+        assert!(matches!(device.decrypt_to_command(&encrypted), Err(CryptoError::UnknownKeyId(9))));
+    }
+}
@@ -1,35 +1,125 @@
 //! # ESP32 Tauri 暗号化通信ライブラリ
 //!
-//! ESP32とTauriアプリケーション間でAES-256-GCMを使用した
+//! ESP32とTauriアプリケーション間でAEAD暗号を使用した
 //! 軽量暗号化通信を行うためのライブラリです。
 //!
 //! ## 特徴
-//! - AES-256-GCM暗号化
-//! - SHA-256固定鍵による簡単なセットアップ
+//! - AES-256-GCM / ChaCha20-Poly1305 / XChaCha20-Poly1305 から選択可能な暗号スイート
+//! - PBKDF2-HMAC-SHA256（ソルト付き）によるパスフレーズからの鍵導出
+//! - X25519エフェメラル鍵交換によるセッション鍵（前方秘匿性）
+//! - HKDFによる方向別サブ鍵（反射攻撃対策）
 //! - ESP32とTauriの両方で使用可能
-//! - Base64エンコーディングによる安全なデータ転送
+//! - JSON（Base64）とCBORから選択可能なワイヤーコーデック
+//! - COBS + CRC-32によるフレーミング（ログ出力や破損からの再同期）
+//! - 任意の `Read + Write` 上で暗号化メッセージを送受信する `SecureChannel`
+//!   （`async` 機能でtokio版の `AsyncSecureChannel`）
+//! - `esp32` / `tauri` 機能でUART・シリアルポートのトランスポート（`transport`）
+//! - 型付きのリクエスト `Request`（従来の `Command` と相互変換）
+//! - `ResponseStatus` / `ErrorCode` と構造化ペイロードを持つ `Response`
+//! - 接続時にファームウェアの機能を確認する `DeviceInfo`（`capabilities`）
+//! - `status` リクエストで報告するヒープ・稼働時間・リセット要因などの `DeviceStatus`（`status`）
+//! - `ts` 機能でプロトコルの型からTypeScriptの型定義を生成（ts-rs）
 //!
 //! ## 使用例
 //!
 //! ```rust
 //! use esp32_tauri_crypto::*;
 //!
-//! // 暗号化システムの初期化
-//! let crypto = CryptoSystem::new("MY_SECRET_KEY_2025");
+//! # fn main() -> Result<(), CryptoError> {
 //!
-//! // メッセージの暗号化
+//! // 暗号化システムの初期化（導出パラメータはプロビジョニング時に生成して両端で共有）
+//! let params = KdfParams::generate(kdf::DEFAULT_ITERATIONS);
+//! let host = CryptoSystem::derive("MY_SECRET_KEY_2025", &params, Role::Host)?;
+//! let device = CryptoSystem::derive("MY_SECRET_KEY_2025", &params, Role::Device)?;
+//!
+//! // メッセージの暗号化（ホスト→デバイス）
 //! let message = "Hello ESP32!";
-//! let encrypted = crypto.encrypt(message)?;
+//! let encrypted = host.encrypt(message)?;
 //!
 //! // メッセージの復号化
-//! let decrypted = crypto.decrypt(&encrypted)?;
+//! let decrypted = device.decrypt(&encrypted)?;
+//! # assert_eq!(decrypted, message);
+//! # Ok(())
+//! # }
 //! ```
 
 use serde::{Deserialize, Serialize};
-use aes_gcm::{Aes256Gcm, Nonce, aead::{Aead, KeyInit}};
-use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
-use sha2::{Sha256, Digest};
-use rand_core::{OsRng, RngCore};
+use std::sync::{Arc, Mutex};
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+#[cfg(feature = "async")]
+pub mod async_channel;
+pub mod capabilities;
+pub mod channel;
+pub mod cipher;
+pub mod codec;
+pub mod framing;
+pub mod handshake;
+pub mod kdf;
+pub mod keyring;
+pub mod nonce;
+pub mod protocol;
+pub mod replay;
+pub mod status;
+pub mod transport;
+
+#[cfg(feature = "async")]
+pub use async_channel::AsyncSecureChannel;
+pub use capabilities::{CommandInfo, Compatibility, DeviceInfo, Privilege};
+pub use channel::{ChannelError, Packet, SecureChannel};
+pub use cipher::CipherSuite;
+pub use codec::WireCodec;
+pub use framing::{FrameDecoder, FrameEvent};
+pub use kdf::KdfParams;
+pub use keyring::Keyring;
+pub use nonce::NonceStrategy;
+pub use protocol::{ErrorCode, Request, ResponseStatus};
+pub use status::{ChipInfo, DeviceStatus, HeapStats, ResetReason, TaskStack};
+pub use replay::ReplayWindow;
+
+/// プロトコルバージョン（認証データのヘッダに含まれる）
+///
+/// v2でハンドシェイクのMACの入力を長さで区切る形式に変更したため、v1の相手とはハンドシェイクできません。
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// 暗号化されるメッセージの種別
+///
+/// 種別はヘッダとして認証データに束縛されるため、
+/// レスポンスをコマンドとして復号化することはできません。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// ホスト→デバイスのコマンド
+    Command = 1,
+    /// デバイス→ホストのレスポンス
+    Response = 2,
+}
+
+impl MessageKind {
+    /// 認証データとして束縛するヘッダ（マジック・バージョン・種別）
+    pub fn header(self) -> [u8; 4] {
+        [b'E', b'T', PROTOCOL_VERSION, self as u8]
+    }
+}
+
+/// 方向別サブ鍵導出のinfo（ホスト→デバイス）
+const HOST_TO_DEVICE_INFO: &[u8] = b"esp32-tauri host->device";
+/// 方向別サブ鍵導出のinfo（デバイス→ホスト）
+const DEVICE_TO_HOST_INFO: &[u8] = b"esp32-tauri device->host";
+
+/// 通信上の役割
+///
+/// 役割ごとに送信用・受信用のサブ鍵が入れ替わるため、
+/// 自分が送信したフレームを反射されても復号化できません。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// ホスト（Tauriアプリ）
+    Host,
+    /// デバイス（ESP32）
+    Device,
+}
 
 /// 暗号化エラーの種類
 #[derive(Debug)]
@@ -44,6 +134,22 @@ pub enum CryptoError {
     Base64DecodeFailed,
     /// UTF-8デコードに失敗
     Utf8DecodeFailed,
+    /// 鍵交換ハンドシェイクの検証に失敗
+    HandshakeFailed,
+    /// 再送された、または古すぎるメッセージ
+    ReplayDetected,
+    /// メッセージの鍵IDに対応する鍵がない
+    UnknownKeyId(u32),
+    /// 設定と異なる、またはネゴシエーションできない暗号スイート
+    UnsupportedCipherSuite,
+    /// 送信メッセージ数が鍵の上限に達したため再鍵交換が必要
+    RekeyRequired,
+    /// 鍵の種類と組み合わせられないnonce方式
+    UnsupportedNonceStrategy,
+    /// メッセージのシリアライズ・デシリアライズに失敗
+    InvalidFormat,
+    /// フレームのCOBSデコードまたはCRC検証に失敗
+    InvalidFrame,
 }
 
 impl std::fmt::Display for CryptoError {
@@ -54,6 +160,14 @@ impl std::fmt::Display for CryptoError {
             CryptoError::KeyCreationFailed => write!(f, "暗号鍵の作成に失敗しました"),
             CryptoError::Base64DecodeFailed => write!(f, "Base64デコードに失敗しました"),
             CryptoError::Utf8DecodeFailed => write!(f, "UTF-8デコードに失敗しました"),
+            CryptoError::HandshakeFailed => write!(f, "鍵交換ハンドシェイクの検証に失敗しました"),
+            CryptoError::ReplayDetected => write!(f, "再送または古いメッセージを検出しました"),
+            CryptoError::UnknownKeyId(id) => write!(f, "鍵ID {} に対応する鍵がありません", id),
+            CryptoError::UnsupportedCipherSuite => write!(f, "サポートされていない暗号スイートです"),
+            CryptoError::RekeyRequired => write!(f, "送信数が上限に達しました。鍵交換をやり直してください"),
+            CryptoError::UnsupportedNonceStrategy => write!(f, "カウンタ方式のnonceはセッション鍵でのみ使用できます"),
+            CryptoError::InvalidFormat => write!(f, "メッセージ形式が不正です"),
+            CryptoError::InvalidFrame => write!(f, "フレームが破損しています"),
         }
     }
 }
@@ -61,62 +175,376 @@ impl std::fmt::Display for CryptoError {
 impl std::error::Error for CryptoError {}
 
 /// 暗号化されたメッセージを表す構造体
+///
+/// JSONでは `ciphertext` と `nonce` はBase64文字列、CBORではバイト列になります。
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct EncryptedMessage {
-    /// Base64エンコードされた暗号文
-    pub ciphertext: String,
-    /// Base64エンコードされたnonce（初期化ベクトル）
-    pub nonce: String,
+    /// 暗号文（認証タグを含む）
+    #[serde(with = "codec::base64_bytes")]
+    #[cfg_attr(feature = "ts", ts(type = "string"))]
+    pub ciphertext: Vec<u8>,
+    /// nonce（初期化ベクトル）
+    #[serde(with = "codec::base64_bytes")]
+    #[cfg_attr(feature = "ts", ts(type = "string"))]
+    pub nonce: Vec<u8>,
+    /// 送信側のシーケンス番号（1から単調増加、認証データに含まれる）
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub seq: u64,
+    /// 暗号化に使用した鍵のID（認証データに含まれる）
+    #[serde(default)]
+    pub key_id: u32,
+    /// 暗号化に使用した暗号スイート（認証データに含まれる）
+    #[serde(default)]
+    pub suite: CipherSuite,
 }
 
 /// コマンド構造体（ESP32-Tauri通信用）
+///
+/// 引数は用途に応じて `params`（構造化されたパラメータ）と `binary`（バイト列）で渡します。
+/// `data` は従来形式の文字列で、互換性のために残しています。
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct Command {
     /// アクション名
     pub action: String,
-    /// オプションのデータ
+    /// オプションのデータ（従来形式の文字列）
     pub data: Option<String>,
+    /// リクエストID（レスポンスとの対応付け用、省略可）
+    #[serde(default)]
+    pub id: Option<u32>,
+    /// 構造化されたパラメータ（JSONオブジェクト）
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+    /// バイナリデータ（設定データ・ファイルの断片・I2Cの書き込みデータなど）
+    ///
+    /// JSONではBase64文字列、CBORではバイト列のまま格納されます。
+    #[serde(default, with = "codec::optional_base64_bytes")]
+    #[cfg_attr(feature = "ts", ts(type = "string | null"))]
+    pub binary: Option<Vec<u8>>,
+}
+
+impl Command {
+    /// アクション名からコマンドを作成
+    pub fn new(action: impl Into<String>) -> Self {
+        Self {
+            action: action.into(),
+            data: None,
+            id: None,
+            params: None,
+            binary: None,
+        }
+    }
+
+    /// 従来形式の文字列データを設定
+    pub fn with_data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// リクエストIDを設定
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// 構造化されたパラメータを設定
+    pub fn with_params(mut self, params: serde_json::Value) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    /// バイナリデータを設定
+    pub fn with_binary(mut self, binary: impl Into<Vec<u8>>) -> Self {
+        self.binary = Some(binary.into());
+        self
+    }
 }
 
 /// レスポンス構造体（ESP32-Tauri通信用）
+///
+/// 処理結果は `status` と `code` で判定し、`message` は表示用として扱います。
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct Response {
     /// ステータス
-    pub status: String,
-    /// メッセージ内容
+    pub status: ResponseStatus,
+    /// メッセージ内容（表示用）
     pub message: String,
     /// 応答元のコマンド
     pub response_to: Option<String>,
+    /// 応答元のコマンドのリクエストID（起動通知など要求に対応しない場合は `None`）
+    #[serde(default)]
+    pub id: Option<u32>,
+    /// エラーコード（`status` が `Error` の場合）
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    /// 構造化されたペイロード（リクエストごとの結果）
+    #[serde(default)]
+    pub payload: Option<serde_json::Value>,
+}
+
+impl Response {
+    /// ステータスとメッセージからレスポンスを作成
+    pub fn new(status: ResponseStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            response_to: None,
+            id: None,
+            code: None,
+            payload: None,
+        }
+    }
+
+    /// 成功レスポンスを作成
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self::new(ResponseStatus::Ok, message)
+    }
+
+    /// エラーレスポンスを作成
+    ///
+    /// # 引数
+    /// * `code` - 機械可読なエラーコード
+    /// * `message` - 表示用のメッセージ
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code: Some(code),
+            ..Self::new(ResponseStatus::Error, message)
+        }
+    }
+
+    /// リクエストに対応しない通知を作成
+    pub fn event(message: impl Into<String>) -> Self {
+        Self::new(ResponseStatus::Event, message)
+    }
+
+    /// 応答元のコマンド（アクション名とリクエストID）を設定
+    pub fn in_reply_to(mut self, command: &Command) -> Self {
+        self.response_to = Some(command.action.clone());
+        self.id = command.id;
+        self
+    }
+
+    /// 構造化されたペイロードを設定
+    pub fn with_payload(mut self, payload: serde_json::Value) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// エラーレスポンスか
+    pub fn is_error(&self) -> bool {
+        self.status == ResponseStatus::Error
+    }
+}
+
+/// 送受信のシーケンス番号の状態
+#[derive(Debug)]
+struct SequenceState {
+    /// 次に送信するシーケンス番号
+    next_send: u64,
+    /// 受信側のリプレイウィンドウ
+    window: ReplayWindow,
+}
+
+impl Default for SequenceState {
+    fn default() -> Self {
+        Self { next_send: 1, window: ReplayWindow::new() }
+    }
 }
 
 /// 暗号化通信システムのメイン構造体
+///
+/// クローンはシーケンス番号の状態を共有するため、
+/// 複数スレッドから同じセッションを使っても番号が重複しません。
 #[derive(Clone)]
 pub struct CryptoSystem {
-    /// 暗号化鍵（AES-256用の32バイト）
+    /// マスター鍵（方向別サブ鍵とハンドシェイク認証の元になる32バイト）
     key: [u8; 32],
+    /// 鍵ID（鍵ローテーション時の識別用）
+    key_id: u32,
+    /// 自分の役割
+    role: Role,
+    /// 暗号スイート
+    suite: CipherSuite,
+    /// nonceの生成方式
+    nonce_strategy: NonceStrategy,
+    /// カウンタ方式のnonceプレフィックス（セッションごとにランダム）
+    nonce_prefix: [u8; nonce::MAX_PREFIX_LEN],
+    /// ハンドシェイクで確立した使い捨てのセッション鍵か
+    session_key: bool,
+    /// 送信メッセージ数の上限（`None` はnonce方式とスイートから決まる上限）
+    rekey_limit: Option<u64>,
+    /// コマンド・レスポンスのシリアライズ形式
+    codec: WireCodec,
+    /// 送信用サブ鍵
+    send_key: [u8; 32],
+    /// 受信用サブ鍵
+    recv_key: [u8; 32],
+    /// パスフレーズから導出した場合の導出パラメータ
+    kdf: Option<KdfParams>,
+    /// 送信カウンタとリプレイウィンドウ（クローン間で共有）
+    sequence: Arc<Mutex<SequenceState>>,
 }
 
 impl CryptoSystem {
-    /// 固定文字列から暗号化システムを作成
-    /// 
+    /// パスフレーズから暗号化システムを作成（非推奨）
+    ///
+    /// ライブラリに埋め込まれた公開の固定ソルトで鍵を導出するため、
+    /// 1つの事前計算表で同じ方式のすべての鍵を探索できます。
+    /// デプロイメントごとに生成した [`KdfParams`] で [`CryptoSystem::derive`] を使用してください。
+    #[deprecated(note = "uses a public fixed salt; use CryptoSystem::derive with provisioned KdfParams")]
+    pub fn new(seed: &str, role: Role) -> Self {
+        Self::derive(seed, &kdf::DEMO_PARAMS, role)
+            .expect("demo KDF parameters are always valid")
+    }
+
+    /// 指定した導出パラメータでパスフレーズから暗号化システムを作成
+    ///
     /// # 引数
-    /// - `seed`: 鍵生成用の種文字列
-    /// 
+    /// - `passphrase`: パスフレーズ
+    /// - `params`: ソルトと反復回数（デプロイメントごとに生成し、両端で同じものを使用）
+    /// - `role`: 自分の役割
+    ///
     /// # 例
     /// ```rust
-    /// let crypto = CryptoSystem::new("ESP32_TAURI_DEMO_KEY_2025");
+    /// # use esp32_tauri_crypto::{CryptoSystem, KdfParams, Role};
+    /// let params = KdfParams::generate(esp32_tauri_crypto::kdf::MIN_ITERATIONS);
+    /// let crypto = CryptoSystem::derive("operator passphrase", &params, Role::Device)?;
+    /// assert_eq!(crypto.kdf_params(), Some(&params));
+    /// # Ok::<(), esp32_tauri_crypto::CryptoError>(())
     /// ```
-    pub fn new(seed: &str) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(seed.as_bytes());
-        let key: [u8; 32] = hasher.finalize().into();
-        
-        Self { key }
+    pub fn derive(passphrase: &str, params: &KdfParams, role: Role) -> Result<Self, CryptoError> {
+        let key = params.derive_key(passphrase)?;
+        let mut crypto = Self::from_key(key, role);
+        crypto.kdf = Some(params.clone());
+        Ok(crypto)
+    }
+
+    /// ハンドシェイクで導出したセッション鍵から暗号化システムを作成
+    ///
+    /// 接続ごとに新しい鍵のため、カウンタ方式のnonceを使えます。
+    pub(crate) fn from_session_key(key: [u8; 32], role: Role) -> Self {
+        let mut crypto = Self::from_key(key, role);
+        crypto.session_key = true;
+        crypto
     }
 
     /// 32バイトの直接的な鍵から暗号化システムを作成
-    pub fn from_key(key: [u8; 32]) -> Self {
-        Self { key }
+    ///
+    /// マスター鍵からHKDF-SHA256で方向別のサブ鍵を導出し、
+    /// `role` に応じて送信用・受信用を割り当てます。
+    pub fn from_key(key: [u8; 32], role: Role) -> Self {
+        let host_to_device = derive_subkey(&key, HOST_TO_DEVICE_INFO);
+        let device_to_host = derive_subkey(&key, DEVICE_TO_HOST_INFO);
+        let (send_key, recv_key) = match role {
+            Role::Host => (host_to_device, device_to_host),
+            Role::Device => (device_to_host, host_to_device),
+        };
+
+        Self {
+            key,
+            key_id: 0,
+            role,
+            suite: CipherSuite::default(),
+            nonce_strategy: NonceStrategy::default(),
+            nonce_prefix: [0u8; nonce::MAX_PREFIX_LEN],
+            session_key: false,
+            rekey_limit: None,
+            codec: WireCodec::default(),
+            send_key,
+            recv_key,
+            kdf: None,
+            sequence: Arc::default(),
+        }
+    }
+
+    /// 鍵IDを設定（デフォルトは0）
+    ///
+    /// 鍵IDは暗号化したメッセージに記録され、[`Keyring`] が復号化に使う鍵の選択に使用します。
+    pub fn with_key_id(mut self, key_id: u32) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    /// 鍵ID
+    pub fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    /// 暗号スイートを設定（デフォルトはAES-256-GCM）
+    ///
+    /// 両端で同じスイートを設定するか、ハンドシェイクでネゴシエーションしてください。
+    pub fn with_cipher_suite(mut self, suite: CipherSuite) -> Self {
+        self.suite = suite;
+        self
+    }
+
+    /// 暗号スイート
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    /// nonceの生成方式を設定（デフォルトはランダム）
+    ///
+    /// カウンタ方式を選ぶと、新しいランダムなnonceプレフィックスを生成します。
+    ///
+    /// # エラー
+    /// 送信カウンタはRAMにしかないため、再起動後も同じ鍵を使うPSKでカウンタ方式を選ぶと
+    /// nonceが再利用されます。カウンタ方式はハンドシェイクで確立したセッション鍵でのみ使え、
+    /// それ以外では `UnsupportedNonceStrategy` を返します。
+    pub fn with_nonce_strategy(mut self, strategy: NonceStrategy) -> Result<Self, CryptoError> {
+        if strategy == NonceStrategy::Counter {
+            if !self.session_key {
+                return Err(CryptoError::UnsupportedNonceStrategy);
+            }
+            self.nonce_prefix = nonce::random_prefix();
+        }
+        self.nonce_strategy = strategy;
+        Ok(self)
+    }
+
+    /// nonceの生成方式
+    pub fn nonce_strategy(&self) -> NonceStrategy {
+        self.nonce_strategy
+    }
+
+    /// 送信メッセージ数の上限を設定
+    ///
+    /// 上限に達すると暗号化は `RekeyRequired` を返します。
+    /// nonce方式とスイートから決まる上限より大きい値は無視されます。
+    pub fn with_rekey_limit(mut self, limit: u64) -> Self {
+        self.rekey_limit = Some(limit);
+        self
+    }
+
+    /// コマンド・レスポンスのシリアライズ形式を設定（デフォルトはJSON）
+    ///
+    /// 両端で同じコーデックを設定してください。
+    pub fn with_codec(mut self, codec: WireCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// コマンド・レスポンスのシリアライズ形式
+    pub fn codec(&self) -> WireCodec {
+        self.codec
+    }
+
+    /// 送信メッセージ数の上限
+    pub fn message_limit(&self) -> u64 {
+        let limit = self.nonce_strategy.message_limit(self.suite);
+        self.rekey_limit.map_or(limit, |custom| custom.min(limit))
+    }
+
+    /// 自分の役割
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// 鍵の導出に使用したパラメータ（`from_key` で作成した場合は `None`）
+    pub fn kdf_params(&self) -> Option<&KdfParams> {
+        self.kdf.as_ref()
     }
 
     /// 文字列を暗号化
@@ -127,22 +555,7 @@ impl CryptoSystem {
     /// # 戻り値
     /// 暗号化されたメッセージまたはエラー
     pub fn encrypt(&self, plaintext: &str) -> Result<EncryptedMessage, CryptoError> {
-        let cipher = Aes256Gcm::new_from_slice(&self.key)
-            .map_err(|_| CryptoError::KeyCreationFailed)?;
-        
-        // ランダムなnonce生成（12バイト）
-        let mut nonce_bytes = [0u8; 12];
-        OsRng.fill_bytes(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
-        
-        // 暗号化実行
-        let ciphertext = cipher.encrypt(nonce, plaintext.as_bytes())
-            .map_err(|_| CryptoError::EncryptionFailed)?;
-        
-        Ok(EncryptedMessage {
-            ciphertext: BASE64.encode(&ciphertext),
-            nonce: BASE64.encode(&nonce_bytes),
-        })
+        self.encrypt_with_aad(plaintext, &[])
     }
 
     /// 暗号化されたメッセージを復号化
@@ -153,55 +566,160 @@ impl CryptoSystem {
     /// # 戻り値
     /// 復号化された文字列またはエラー
     pub fn decrypt(&self, encrypted: &EncryptedMessage) -> Result<String, CryptoError> {
-        let cipher = Aes256Gcm::new_from_slice(&self.key)
-            .map_err(|_| CryptoError::KeyCreationFailed)?;
+        self.decrypt_with_aad(encrypted, &[])
+    }
+
+    /// 認証データ（AAD）付きで文字列を暗号化
+    ///
+    /// `aad` は暗号化されませんが改ざん検知の対象になり、
+    /// 復号化時に同じ値を渡さないと失敗します。
+    ///
+    /// # 引数
+    /// - `plaintext`: 暗号化したい文字列
+    /// - `aad`: 暗号文に束縛する認証データ
+    pub fn encrypt_with_aad(&self, plaintext: &str, aad: &[u8]) -> Result<EncryptedMessage, CryptoError> {
+        self.seal(plaintext.as_bytes(), aad)
+    }
+
+    /// 認証データ（AAD）付きで暗号化されたメッセージを復号化
+    ///
+    /// # 引数
+    /// - `encrypted`: 暗号化されたメッセージ
+    /// - `aad`: 暗号化時に指定した認証データ
+    pub fn decrypt_with_aad(&self, encrypted: &EncryptedMessage, aad: &[u8]) -> Result<String, CryptoError> {
+        let plaintext = self.open(encrypted, aad)?;
+        String::from_utf8(plaintext)
+            .map_err(|_| CryptoError::Utf8DecodeFailed)
+    }
+
+    /// バイト列を暗号化
+    /// 
+    /// 送信ごとにシーケンス番号を1つ進め、`aad` と共に認証データとして暗号文に束縛します。
+    fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<EncryptedMessage, CryptoError> {
+        let seq = {
+            let mut sequence = self.sequence.lock()
+                .map_err(|_| CryptoError::EncryptionFailed)?;
+            let seq = sequence.next_send;
+            if seq > self.message_limit() {
+                return Err(CryptoError::RekeyRequired);
+            }
+            sequence.next_send = seq + 1;
+            seq
+        };
+        
+        // nonce生成（スイートに応じて12または24バイト）
+        let nonce_bytes = self.nonce_strategy.nonce(self.suite, &self.nonce_prefix, seq);
         
-        let nonce_bytes = BASE64.decode(&encrypted.nonce)
-            .map_err(|_| CryptoError::Base64DecodeFailed)?;
-        let nonce = Nonce::from_slice(&nonce_bytes);
+        // 暗号化実行（シーケンス番号・鍵ID・スイートを認証データに含める）
+        let aad = self.full_aad(seq, aad);
+        let ciphertext = self.suite.seal(&self.send_key, &nonce_bytes, &aad, plaintext)?;
         
-        let ciphertext = BASE64.decode(&encrypted.ciphertext)
-            .map_err(|_| CryptoError::Base64DecodeFailed)?;
+        Ok(EncryptedMessage {
+            ciphertext,
+            nonce: nonce_bytes,
+            seq,
+            key_id: self.key_id,
+            suite: self.suite,
+        })
+    }
+
+    /// バイト列を復号化
+    /// 
+    /// 認証に成功したシーケンス番号のみをリプレイウィンドウに記録し、
+    /// 同じメッセージの再受信は `ReplayDetected` で拒否します。
+    fn open(&self, encrypted: &EncryptedMessage, aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if encrypted.key_id != self.key_id {
+            return Err(CryptoError::UnknownKeyId(encrypted.key_id));
+        }
+        if encrypted.suite != self.suite {
+            return Err(CryptoError::UnsupportedCipherSuite);
+        }
         
-        let plaintext = cipher.decrypt(nonce, ciphertext.as_ref())
+        let mut sequence = self.sequence.lock()
             .map_err(|_| CryptoError::DecryptionFailed)?;
+        sequence.window.check(encrypted.seq)?;
         
-        String::from_utf8(plaintext)
-            .map_err(|_| CryptoError::Utf8DecodeFailed)
+        let aad = self.full_aad(encrypted.seq, aad);
+        let plaintext = self.suite.open(&self.recv_key, &encrypted.nonce, &aad, &encrypted.ciphertext)?;
+        sequence.window.accept(encrypted.seq);
+        
+        Ok(plaintext)
     }
 
-    /// コマンドをJSON形式で暗号化
+    /// シーケンス番号・鍵ID・スイートと呼び出し側の認証データを連結
+    fn full_aad(&self, seq: u64, aad: &[u8]) -> Vec<u8> {
+        let mut full = Vec::with_capacity(13 + aad.len());
+        full.extend_from_slice(&seq.to_be_bytes());
+        full.extend_from_slice(&self.key_id.to_be_bytes());
+        full.push(self.suite.id());
+        full.extend_from_slice(aad);
+        full
+    }
+
+    /// コマンドをコーデックでシリアライズして暗号化（コマンド種別のヘッダを束縛）
     pub fn encrypt_command(&self, command: &Command) -> Result<EncryptedMessage, CryptoError> {
-        let json = serde_json::to_string(command)
-            .map_err(|_| CryptoError::EncryptionFailed)?;
-        self.encrypt(&json)
+        let bytes = self.codec.encode(command)?;
+        self.seal(&bytes, &MessageKind::Command.header())
     }
 
-    /// レスポンスをJSON形式で暗号化
+    /// レスポンスをコーデックでシリアライズして暗号化（レスポンス種別のヘッダを束縛）
     pub fn encrypt_response(&self, response: &Response) -> Result<EncryptedMessage, CryptoError> {
-        let json = serde_json::to_string(response)
-            .map_err(|_| CryptoError::EncryptionFailed)?;
-        self.encrypt(&json)
+        let bytes = self.codec.encode(response)?;
+        self.seal(&bytes, &MessageKind::Response.header())
     }
 
     /// 暗号化されたメッセージからコマンドを復号化
     pub fn decrypt_to_command(&self, encrypted: &EncryptedMessage) -> Result<Command, CryptoError> {
-        let json = self.decrypt(encrypted)?;
-        serde_json::from_str(&json)
-            .map_err(|_| CryptoError::DecryptionFailed)
+        let bytes = self.open(encrypted, &MessageKind::Command.header())?;
+        self.codec.decode(&bytes)
     }
 
     /// 暗号化されたメッセージからレスポンスを復号化
     pub fn decrypt_to_response(&self, encrypted: &EncryptedMessage) -> Result<Response, CryptoError> {
-        let json = self.decrypt(encrypted)?;
-        serde_json::from_str(&json)
-            .map_err(|_| CryptoError::DecryptionFailed)
+        let bytes = self.open(encrypted, &MessageKind::Response.header())?;
+        self.codec.decode(&bytes)
     }
 }
 
-/// 便利関数：デフォルトのシード文字列を使用して暗号化システムを作成
-pub fn create_default_crypto() -> CryptoSystem {
-    CryptoSystem::new("ESP32_TAURI_DEMO_KEY_2025")
+/// マスター鍵から方向別のサブ鍵を導出
+fn derive_subkey(key: &[u8; 32], info: &[u8]) -> [u8; 32] {
+    let mut subkey = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(info, &mut subkey)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    subkey
+}
+
+/// プロビジョニングした事前共有鍵のパスフレーズを渡す環境変数
+///
+/// ホスト（GUI・シミュレータ）は実行時に、ファームウェアはビルド時に読み取ります。
+pub const PASSPHRASE_ENV: &str = "ESP32_TAURI_PASSPHRASE";
+
+/// プロビジョニングした鍵導出パラメータ（[`KdfParams`] のJSON）を渡す環境変数
+pub const KDF_PARAMS_ENV: &str = "ESP32_TAURI_KDF_PARAMS";
+
+/// 便利関数：デモ用の固定パスフレーズで暗号化システムを作成
+///
+/// パスフレーズとソルトがソースコードに含まれているため、デモとシミュレータ専用です。
+/// 製品では [`create_provisioned_crypto`] を使用してください。
+pub fn create_default_crypto(role: Role) -> CryptoSystem {
+    // 導出は重いため、プロセス内で一度だけ行う
+    static DEMO_KEY: std::sync::OnceLock<[u8; 32]> = std::sync::OnceLock::new();
+    let key = DEMO_KEY.get_or_init(|| {
+        kdf::DEMO_PARAMS.derive_key("ESP32_TAURI_DEMO_KEY_2025").expect("demo KDF parameters are always valid")
+    });
+    let mut crypto = CryptoSystem::from_key(*key, role);
+    crypto.kdf = Some(kdf::DEMO_PARAMS);
+    crypto
+}
+
+/// 便利関数：プロビジョニングしたパスフレーズと導出パラメータ（[`KdfParams`] のJSON）で暗号化システムを作成
+///
+/// # エラー
+/// JSONが不正な場合や反復回数が [`kdf::MIN_ITERATIONS`] 未満の場合は `KeyCreationFailed`
+pub fn create_provisioned_crypto(passphrase: &str, kdf_params_json: &str, role: Role) -> Result<CryptoSystem, CryptoError> {
+    let params: KdfParams = serde_json::from_str(kdf_params_json).map_err(|_| CryptoError::KeyCreationFailed)?;
+    CryptoSystem::derive(passphrase, &params, role)
 }
 
 /// 現在のタイムスタンプを取得（UNIX時間）
@@ -216,29 +734,200 @@ pub fn get_current_timestamp() -> u64 {
 mod tests {
     use super::*;
 
+    const TEST_KEY: [u8; 32] = [7u8; 32];
+
     #[test]
     fn test_crypto_round_trip() {
-        let crypto = CryptoSystem::new("test_key");
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let device = CryptoSystem::from_key(TEST_KEY, Role::Device);
         let message = "Hello, World!";
         
-        let encrypted = crypto.encrypt(message).unwrap();
-        let decrypted = crypto.decrypt(&encrypted).unwrap();
+        let encrypted = host.encrypt(message).unwrap();
+        let decrypted = device.decrypt(&encrypted).unwrap();
         
         assert_eq!(message, decrypted);
     }
 
     #[test]
     fn test_command_encryption() {
-        let crypto = CryptoSystem::new("test_key");
-        let command = Command {
-            action: "hello".to_string(),
-            data: Some("test data".to_string()),
-        };
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let device = CryptoSystem::from_key(TEST_KEY, Role::Device);
+        let command = Command::new("hello").with_data("test data").with_id(7);
         
-        let encrypted = crypto.encrypt_command(&command).unwrap();
-        let decrypted = crypto.decrypt_to_command(&encrypted).unwrap();
+        let encrypted = host.encrypt_command(&command).unwrap();
+        let decrypted = device.decrypt_to_command(&encrypted).unwrap();
         
         assert_eq!(command.action, decrypted.action);
         assert_eq!(command.data, decrypted.data);
+        assert_eq!(decrypted.id, Some(7));
+    }
+
+    #[test]
+    fn test_messages_without_id_are_accepted() {
+        let command: Command = serde_json::from_str(r#"{"action":"ping","data":null}"#).unwrap();
+        assert_eq!(command.id, None);
+        assert_eq!(command.with_id(3).id, Some(3));
+
+        let response: Response = serde_json::from_str(r#"{"status":"event","message":"ok","response_to":null}"#).unwrap();
+        assert_eq!(response.id, None);
+    }
+
+    #[test]
+    fn test_error_response_carries_code_and_payload() {
+        let command = Command::new("echo").with_id(5);
+        let response = Response::error(ErrorCode::InvalidParameters, "Invalid parameters")
+            .in_reply_to(&command)
+            .with_payload(serde_json::json!({ "missing": "text" }));
+
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host).with_codec(WireCodec::Cbor);
+        let device = CryptoSystem::from_key(TEST_KEY, Role::Device).with_codec(WireCodec::Cbor);
+        let decrypted = host.decrypt_to_response(&device.encrypt_response(&response).unwrap()).unwrap();
+
+        assert!(decrypted.is_error());
+        assert_eq!(decrypted.code, Some(ErrorCode::InvalidParameters));
+        assert_eq!(decrypted.response_to.as_deref(), Some("echo"));
+        assert_eq!(decrypted.id, Some(5));
+        assert_eq!(decrypted.payload, Some(serde_json::json!({ "missing": "text" })));
+    }
+
+    #[test]
+    fn test_reflected_message_is_rejected() {
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let command = Command::new("ping");
+
+        // ホストが送信したフレームをホスト自身に反射しても復号化できない
+        let encrypted = host.encrypt_command(&command).unwrap();
+        assert!(matches!(host.decrypt_to_command(&encrypted), Err(CryptoError::DecryptionFailed)));
+
+        let other_host = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        assert!(other_host.decrypt_to_command(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_cipher_suites_round_trip_and_must_match() {
+        for suite in CipherSuite::ALL {
+            let host = CryptoSystem::from_key(TEST_KEY, Role::Host).with_cipher_suite(suite);
+            let device = CryptoSystem::from_key(TEST_KEY, Role::Device).with_cipher_suite(suite);
+
+            let encrypted = host.encrypt("hello").unwrap();
+            assert_eq!(encrypted.suite, suite);
+            assert_eq!(device.decrypt(&encrypted).unwrap(), "hello");
+        }
+
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host).with_cipher_suite(CipherSuite::ChaCha20Poly1305);
+        let device = CryptoSystem::from_key(TEST_KEY, Role::Device);
+        let encrypted = host.encrypt("hello").unwrap();
+        assert!(matches!(device.decrypt(&encrypted), Err(CryptoError::UnsupportedCipherSuite)));
+    }
+
+    #[test]
+    fn test_counter_nonces_are_unique_and_decryptable() {
+        let host = CryptoSystem::from_session_key([5u8; 32], Role::Host).with_nonce_strategy(NonceStrategy::Counter).unwrap();
+        let device = CryptoSystem::from_session_key([5u8; 32], Role::Device);
+
+        let first = host.encrypt("one").unwrap();
+        let second = host.clone().encrypt("two").unwrap();
+        assert_ne!(first.nonce, second.nonce);
+
+        assert_eq!(device.decrypt(&first).unwrap(), "one");
+        assert_eq!(device.decrypt(&second).unwrap(), "two");
+    }
+
+    #[test]
+    fn test_rekey_required_after_limit() {
+        let host = CryptoSystem::from_session_key([5u8; 32], Role::Host)
+            .with_nonce_strategy(NonceStrategy::Counter)
+            .unwrap()
+            .with_rekey_limit(2);
+
+        assert!(host.encrypt("one").is_ok());
+        assert!(host.encrypt("two").is_ok());
+        assert!(matches!(host.encrypt("three"), Err(CryptoError::RekeyRequired)));
+    }
+
+    #[test]
+    fn test_counter_nonce_requires_session_key() {
+        let psk = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        assert!(matches!(psk.clone().with_nonce_strategy(NonceStrategy::Counter), Err(CryptoError::UnsupportedNonceStrategy)));
+        assert!(psk.with_nonce_strategy(NonceStrategy::Random).is_ok());
+    }
+
+    #[test]
+    fn test_command_encryption_with_cbor_codec() {
+        let host = CryptoSystem::from_key(TEST_KEY, Role::Host).with_codec(WireCodec::Cbor);
+        let device = CryptoSystem::from_key(TEST_KEY, Role::Device).with_codec(WireCodec::Cbor);
+        let command = Command::new("status");
+
+        let encrypted = host.encrypt_command(&command).unwrap();
+        assert_eq!(device.decrypt_to_command(&encrypted).unwrap().action, "status");
+    }
+
+    #[test]
+    fn test_aad_must_match() {
+        let sender = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let receiver = CryptoSystem::from_key(TEST_KEY, Role::Device);
+
+        let encrypted = sender.encrypt_with_aad("payload", b"header-a").unwrap();
+        assert!(receiver.decrypt_with_aad(&encrypted, b"header-b").is_err());
+        assert_eq!(receiver.decrypt_with_aad(&encrypted, b"header-a").unwrap(), "payload");
+    }
+
+    #[test]
+    fn test_response_cannot_be_decrypted_as_command() {
+        let sender = CryptoSystem::from_key(TEST_KEY, Role::Device);
+        let receiver = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let response = Response::ok("done").in_reply_to(&Command::new("hello"));
+
+        let encrypted = sender.encrypt_response(&response).unwrap();
+        assert!(matches!(receiver.decrypt_to_command(&encrypted), Err(CryptoError::DecryptionFailed)));
+        assert_eq!(receiver.decrypt_to_response(&encrypted).unwrap().message, "done");
+    }
+
+    #[test]
+    fn test_replayed_message_is_rejected() {
+        let sender = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let receiver = CryptoSystem::from_key(TEST_KEY, Role::Device);
+
+        let first = sender.encrypt("reboot").unwrap();
+        let second = sender.encrypt("reboot").unwrap();
+        assert_eq!(second.seq, first.seq + 1);
+
+        assert!(receiver.decrypt(&first).is_ok());
+        assert!(matches!(receiver.decrypt(&first), Err(CryptoError::ReplayDetected)));
+        assert!(receiver.decrypt(&second).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_sequence_number_fails_authentication() {
+        let sender = CryptoSystem::from_key(TEST_KEY, Role::Host);
+        let receiver = CryptoSystem::from_key(TEST_KEY, Role::Device);
+
+        let mut encrypted = sender.encrypt("toggle").unwrap();
+        encrypted.seq += 1;
+        assert!(matches!(receiver.decrypt(&encrypted), Err(CryptoError::DecryptionFailed)));
+
+        // 認証に失敗したフレームはウィンドウを進めない
+        encrypted.seq -= 1;
+        assert!(receiver.decrypt(&encrypted).is_ok());
+    }
+
+    #[test]
+    fn test_provisioned_crypto_matches_derived_key() {
+        let params = KdfParams::generate(kdf::MIN_ITERATIONS);
+        let json = serde_json::to_string(&params).unwrap();
+        let host = create_provisioned_crypto("passphrase", &json, Role::Host).unwrap();
+        let device = CryptoSystem::derive("passphrase", &params, Role::Device).unwrap();
+
+        assert_eq!(device.decrypt(&host.encrypt("secret").unwrap()).unwrap(), "secret");
+        assert!(create_provisioned_crypto("passphrase", "{}", Role::Host).is_err());
+    }
+
+    #[test]
+    fn test_derived_keys_depend_on_salt() {
+        let a = CryptoSystem::derive("passphrase", &KdfParams::generate(kdf::MIN_ITERATIONS), Role::Host).unwrap();
+        let b = CryptoSystem::derive("passphrase", &KdfParams::generate(kdf::MIN_ITERATIONS), Role::Device).unwrap();
+
+        let encrypted = a.encrypt("secret").unwrap();
+        assert!(b.decrypt(&encrypted).is_err());
     }
 }
\ No newline at end of file
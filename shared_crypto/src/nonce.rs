@@ -0,0 +1,93 @@
+//! # nonce生成方式
+//!
+//! デフォルトでは暗号化のたびに `OsRng` からnonceを生成しますが、
+//! ESP32ではハードウェアRNGが正しくシードされていない（RFが無効など）と
+//! nonceが重複し、GCMでは致命的な脆弱性になります。
+//! [`NonceStrategy::Counter`] はセッションごとのランダムなプレフィックスと
+//! 送信シーケンス番号からnonceを組み立てるため、同じ鍵の下ではRNGの品質に
+//! 依存せずnonceが重複しません。
+//!
+//! 送信カウンタはRAMにしかなく、再起動すると0に戻ります。そのためカウンタ方式は
+//! 接続ごとに新しいハンドシェイクのセッション鍵でのみ使え、再起動後も同じ鍵を使う
+//! PSKでは選べません（[`CryptoSystem::with_nonce_strategy`](crate::CryptoSystem::with_nonce_strategy)）。
+
+use rand_core::{OsRng, RngCore};
+
+use crate::CipherSuite;
+
+/// カウンタ方式で使うプレフィックスの最大長（XChaCha20の24バイトnonce - 8バイトカウンタ）
+pub const MAX_PREFIX_LEN: usize = 16;
+
+/// ランダムな96ビットnonceで安全に暗号化できるメッセージ数の上限（NIST SP 800-38D）
+pub const RANDOM_96BIT_NONCE_LIMIT: u64 = 1 << 32;
+
+/// nonceの生成方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonceStrategy {
+    /// 毎回 `OsRng` からランダムに生成
+    #[default]
+    Random,
+    /// セッションごとのランダムなプレフィックス + 64ビットの送信カウンタ
+    ///
+    /// ハンドシェイクで確立したセッション鍵でのみ使用できます。
+    Counter,
+}
+
+impl NonceStrategy {
+    /// 1つの鍵で暗号化できるメッセージ数の上限
+    ///
+    /// これを超えると `RekeyRequired` になり、ハンドシェイクのやり直しが必要です。
+    pub fn message_limit(self, suite: CipherSuite) -> u64 {
+        match (self, suite.nonce_len()) {
+            (NonceStrategy::Random, 12) => RANDOM_96BIT_NONCE_LIMIT,
+            // カウンタの最大値の次のシーケンス番号が計算できるよう1つ残す
+            _ => u64::MAX - 1,
+        }
+    }
+
+    /// シーケンス番号 `seq` の送信に使うnonceを生成
+    pub(crate) fn nonce(self, suite: CipherSuite, prefix: &[u8; MAX_PREFIX_LEN], seq: u64) -> Vec<u8> {
+        let mut nonce = vec![0u8; suite.nonce_len()];
+        match self {
+            NonceStrategy::Random => OsRng.fill_bytes(&mut nonce),
+            NonceStrategy::Counter => {
+                let prefix_len = nonce.len() - 8;
+                nonce[..prefix_len].copy_from_slice(&prefix[..prefix_len]);
+                nonce[prefix_len..].copy_from_slice(&seq.to_be_bytes());
+            }
+        }
+        nonce
+    }
+}
+
+/// セッションごとのnonceプレフィックスを生成
+pub(crate) fn random_prefix() -> [u8; MAX_PREFIX_LEN] {
+    let mut prefix = [0u8; MAX_PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_nonce_layout() {
+        let prefix = [0xAB; MAX_PREFIX_LEN];
+
+        let nonce = NonceStrategy::Counter.nonce(CipherSuite::Aes256Gcm, &prefix, 0x0102);
+        assert_eq!(nonce, [0xAB, 0xAB, 0xAB, 0xAB, 0, 0, 0, 0, 0, 0, 0x01, 0x02]);
+
+        let nonce = NonceStrategy::Counter.nonce(CipherSuite::XChaCha20Poly1305, &prefix, 1);
+        assert_eq!(nonce.len(), 24);
+        assert_eq!(&nonce[..16], &prefix);
+        assert_eq!(nonce[23], 1);
+    }
+
+    #[test]
+    fn test_message_limits() {
+        assert_eq!(NonceStrategy::Random.message_limit(CipherSuite::Aes256Gcm), RANDOM_96BIT_NONCE_LIMIT);
+        assert_eq!(NonceStrategy::Random.message_limit(CipherSuite::XChaCha20Poly1305), u64::MAX - 1);
+        assert_eq!(NonceStrategy::Counter.message_limit(CipherSuite::ChaCha20Poly1305), u64::MAX - 1);
+    }
+}
@@ -0,0 +1,240 @@
+//! # 型付きリクエスト
+//!
+//! ESP32が受け付けるコマンドを列挙型で表します。
+//! 新しいコマンドはバリアントを追加するだけで、ホストとESP32の両方でコンパイル時に検査されます。
+//!
+//! ワイヤー上では従来の [`Command`] に変換して送信します（`action` がバリアント名、
+//! `params` がパラメータのJSONオブジェクト）。型に変換できない `Command` は
+//! 従来どおり文字列の `action` で処理できます。
+//!
+//! 応答側は [`ResponseStatus`] と [`ErrorCode`] で結果を表し、
+//! ホストはメッセージ文字列を解析せずに処理結果を判定できます。
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use strum::{EnumIter, IntoStaticStr, VariantArray, VariantNames};
+
+use crate::{Command, CryptoError};
+
+/// ESP32へのリクエスト
+///
+/// アクション名は serde のタグと strum の `serialize_all` の両方で同じ snake_case にしています。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter, IntoStaticStr, VariantNames)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Request {
+    /// 挨拶（疎通確認）
+    Hello,
+    /// 応答確認
+    Ping,
+    /// 動作状態の取得
+    Status,
+    /// ファームウェアの機能情報の取得（応答のペイロードは `DeviceInfo`）
+    Info,
+    /// テキストをそのまま返す
+    Echo {
+        /// 返してほしいテキスト
+        text: String,
+    },
+    /// 指定時間後に再起動
+    Reboot {
+        /// 再起動までの待ち時間（ミリ秒）
+        delay_ms: u32,
+    },
+}
+
+impl Request {
+    /// リクエストとして定義されているアクション名
+    pub const ACTIONS: &'static [&'static str] = <Self as VariantNames>::VARIANTS;
+
+    /// アクション名
+    pub fn action(&self) -> &'static str {
+        self.into()
+    }
+
+    /// アクション名がリクエストとして定義されているか
+    pub fn is_known(action: &str) -> bool {
+        Self::ACTIONS.contains(&action)
+    }
+}
+
+/// レスポンスの種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, IntoStaticStr, VariantArray)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ResponseStatus {
+    /// リクエストが成功した
+    #[default]
+    Ok,
+    /// リクエストが失敗した（詳細は [`ErrorCode`]）
+    Error,
+    /// リクエストに対応しない通知（起動通知など）
+    Event,
+    /// 処理中の途中経過（最終的な結果は後続のレスポンスで通知）
+    Progress,
+}
+
+impl ResponseStatus {
+    /// すべての種別
+    pub const ALL: &'static [ResponseStatus] = <Self as VariantArray>::VARIANTS;
+
+    /// ワイヤー上の名前
+    pub fn as_str(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl std::fmt::Display for ResponseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// エラーレスポンスの機械可読なエラーコード
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, IntoStaticStr, VariantArray)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCode {
+    /// 未定義のアクション
+    UnknownCommand,
+    /// パラメータの不足・型の不一致
+    InvalidParameters,
+    /// ハンドシェイクの検証失敗
+    HandshakeFailed,
+    /// 暗号化メッセージの復号化失敗（リプレイを含む）
+    DecryptionFailed,
+    /// 平文のコマンドを受け付けないデバイスに平文で送信した
+    EncryptionRequired,
+    /// コマンドの実行に必要な権限がない（暗号化・セッション鍵が必要）
+    PermissionDenied,
+    /// 短時間に送信されたコマンドが多すぎる
+    RateLimited,
+    /// デバイスが対応していない操作
+    Unsupported,
+    /// トランスポートの読み書きエラー
+    TransportError,
+    /// その他の内部エラー
+    Internal,
+}
+
+impl ErrorCode {
+    /// すべてのエラーコード
+    pub const ALL: &'static [ErrorCode] = <Self as VariantArray>::VARIANTS;
+
+    /// ワイヤー上の名前
+    pub fn as_str(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&Request> for Command {
+    fn from(request: &Request) -> Self {
+        let command = Command::new(request.action());
+        match serde_json::to_value(request) {
+            Ok(Value::Object(mut params)) => {
+                params.remove("action");
+                if params.is_empty() {
+                    command
+                } else {
+                    command.with_params(Value::Object(params))
+                }
+            }
+            _ => command,
+        }
+    }
+}
+
+impl From<Request> for Command {
+    fn from(request: Request) -> Self {
+        Command::from(&request)
+    }
+}
+
+impl TryFrom<&Command> for Request {
+    type Error = CryptoError;
+
+    /// `params` をパラメータのJSONオブジェクトとして解釈して変換
+    ///
+    /// `params` がない場合は、以前の形式（`data` にJSONオブジェクトの文字列）も受け付けます。
+    ///
+    /// # エラー
+    /// 未定義のアクションやパラメータの不足・型の不一致は `CryptoError::InvalidFormat`
+    fn try_from(command: &Command) -> Result<Self, CryptoError> {
+        if !Request::is_known(&command.action) {
+            return Err(CryptoError::InvalidFormat);
+        }
+
+        // パラメータのない従来のコマンドは `data` に自由な文字列を入れていることがある
+        let mut params = match (&command.params, command.data.as_deref().map(serde_json::from_str::<Value>)) {
+            (Some(Value::Object(params)), _) => params.clone(),
+            (None, Some(Ok(Value::Object(params)))) => params,
+            _ => Map::new(),
+        };
+        params.insert("action".to_string(), Value::String(command.action.clone()));
+        serde_json::from_value(Value::Object(params)).map_err(|_| CryptoError::InvalidFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn test_actions_match_every_variant() {
+        let actions: Vec<&str> = Request::iter().map(|request| request.action()).collect();
+        assert_eq!(actions, Request::ACTIONS);
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        for request in Request::iter() {
+            let command = Command::from(&request);
+            assert_eq!(command.action, request.action());
+            assert!(Request::is_known(&command.action));
+            assert_eq!(Request::try_from(&command).unwrap(), request);
+        }
+        let echo = Request::Echo { text: "hi".to_string() };
+        assert_eq!(Request::try_from(&Command::from(&echo)).unwrap(), echo);
+        assert_eq!(Command::from(Request::Ping).params, None);
+        assert_eq!(Command::from(Request::Reboot { delay_ms: 500 }).params, Some(serde_json::json!({ "delay_ms": 500 })));
+    }
+
+    #[test]
+    fn test_legacy_commands() {
+        let legacy = Command::new("hello").with_data("GUI test");
+        assert_eq!(Request::try_from(&legacy).unwrap(), Request::Hello);
+
+        let unknown = Command::new("test_bidirectional");
+        assert!(Request::try_from(&unknown).is_err());
+
+        let missing_params = Command::new("echo");
+        assert!(Request::try_from(&missing_params).is_err());
+
+        // params導入前のホストはパラメータを data に文字列で入れていた
+        let data_params = Command::new("echo").with_data(r#"{"text":"hi"}"#);
+        assert_eq!(Request::try_from(&data_params).unwrap(), Request::Echo { text: "hi".to_string() });
+    }
+
+    #[test]
+    fn test_status_and_error_code_names_match_serde() {
+        for &status in ResponseStatus::ALL {
+            assert_eq!(serde_json::to_value(status).unwrap(), Value::String(status.to_string()));
+            assert_eq!(serde_json::from_value::<ResponseStatus>(Value::String(status.to_string())).unwrap(), status);
+        }
+        for &code in ErrorCode::ALL {
+            assert_eq!(serde_json::to_value(code).unwrap(), Value::String(code.to_string()));
+            assert_eq!(serde_json::from_value::<ErrorCode>(Value::String(code.to_string())).unwrap(), code);
+        }
+    }
+}
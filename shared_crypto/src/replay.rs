@@ -0,0 +1,110 @@
+//! # リプレイ攻撃対策
+//!
+//! 受信したメッセージのシーケンス番号をスライディングウィンドウで管理し、
+//! 同じフレームの再送や古すぎるフレームを拒否します。
+
+use crate::CryptoError;
+
+/// ウィンドウで追跡するシーケンス番号の数
+pub const WINDOW_SIZE: u64 = 64;
+
+/// 受信側のスライディングリプレイウィンドウ
+///
+/// これまでに受信した最大のシーケンス番号と、その直前 [`WINDOW_SIZE`] 個の
+/// 受信済みビットマップを保持します。ウィンドウより古い番号は拒否します。
+#[derive(Debug, Clone, Default)]
+pub struct ReplayWindow {
+    /// 受信済みの最大シーケンス番号（0は未受信）
+    highest: u64,
+    /// bit n が立っていれば `highest - n` を受信済み
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    /// 新しい空のウィンドウを作成
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// シーケンス番号が受理可能か検査（ウィンドウは更新しない）
+    ///
+    /// # エラー
+    /// 0、受信済み、またはウィンドウより古い番号の場合は `ReplayDetected`
+    pub fn check(&self, seq: u64) -> Result<(), CryptoError> {
+        if seq == 0 {
+            return Err(CryptoError::ReplayDetected);
+        }
+        if seq > self.highest {
+            return Ok(());
+        }
+
+        let offset = self.highest - seq;
+        if offset >= WINDOW_SIZE || self.bitmap & (1 << offset) != 0 {
+            return Err(CryptoError::ReplayDetected);
+        }
+        Ok(())
+    }
+
+    /// 認証済みのシーケンス番号をウィンドウに記録
+    ///
+    /// 認証タグの検証に成功した後にのみ呼び出してください。
+    pub fn accept(&mut self, seq: u64) {
+        if seq > self.highest {
+            let shift = seq - self.highest;
+            self.bitmap = if shift >= WINDOW_SIZE { 0 } else { self.bitmap << shift };
+            self.bitmap |= 1;
+            self.highest = seq;
+        } else {
+            self.bitmap |= 1 << (self.highest - seq);
+        }
+    }
+
+    /// 受信済みの最大シーケンス番号
+    pub fn highest(&self) -> u64 {
+        self.highest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(window: &mut ReplayWindow, seq: u64) -> Result<(), CryptoError> {
+        window.check(seq)?;
+        window.accept(seq);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_duplicates() {
+        let mut window = ReplayWindow::new();
+        assert!(receive(&mut window, 1).is_ok());
+        assert!(receive(&mut window, 2).is_ok());
+        assert!(receive(&mut window, 2).is_err());
+        assert!(receive(&mut window, 1).is_err());
+        assert!(receive(&mut window, 0).is_err());
+    }
+
+    #[test]
+    fn test_accepts_reordered_within_window() {
+        let mut window = ReplayWindow::new();
+        assert!(receive(&mut window, 10).is_ok());
+        assert!(receive(&mut window, 7).is_ok());
+        assert!(receive(&mut window, 9).is_ok());
+        assert!(receive(&mut window, 7).is_err());
+        assert_eq!(window.highest(), 10);
+    }
+
+    #[test]
+    fn test_rejects_stale_outside_window() {
+        let mut window = ReplayWindow::new();
+        assert!(receive(&mut window, 100).is_ok());
+        assert!(receive(&mut window, 100 - WINDOW_SIZE + 1).is_ok());
+        assert!(receive(&mut window, 100 - WINDOW_SIZE).is_err());
+
+        // 大きく進んだ後は古いビットが消える
+        assert!(receive(&mut window, 1000).is_ok());
+        assert!(receive(&mut window, 999).is_ok());
+        assert!(receive(&mut window, 900).is_err());
+    }
+}
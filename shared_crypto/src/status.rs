@@ -0,0 +1,173 @@
+//! # デバイスの稼働状態
+//!
+//! ESP32は `status` リクエストの応答のペイロードで [`DeviceStatus`] を報告します。
+//! 現地で不調になった機器を調べるときに最初に確認する情報（ヒープ・稼働時間・
+//! 直前のリセット要因・チップ・タスクのスタック余裕など）をまとめています。
+//!
+//! ```rust
+//! use esp32_tauri_crypto::{DeviceStatus, ResetReason};
+//!
+//! let status: DeviceStatus = serde_json::from_value(serde_json::json!({
+//!     "secure_session": false,
+//!     "cipher_suite": null,
+//!     "firmware_version": "0.1.0",
+//!     "idf_version": "v5.1.2",
+//!     "chip": { "model": "ESP32-S3", "revision": 2, "cores": 2 },
+//!     "uptime_ms": 1500,
+//!     "reset_reason": "panic",
+//!     "heap": { "free_bytes": 200000, "min_free_bytes": 150000 },
+//!     "tasks": [{ "name": "main", "stack_high_water_mark": 2048 }],
+//! })).unwrap();
+//! assert_eq!(status.reset_reason, ResetReason::Panic);
+//! assert!(status.reset_reason.is_abnormal());
+//! ```
+
+use serde::{Deserialize, Serialize};
+use strum::{IntoStaticStr, VariantArray};
+
+use crate::CipherSuite;
+
+/// `status` リクエストの応答のペイロード
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct DeviceStatus {
+    /// セッション鍵が確立しているか
+    pub secure_session: bool,
+    /// 確立したセッション鍵の暗号スイート（ハンドシェイク前は `None`）
+    pub cipher_suite: Option<CipherSuite>,
+    /// ファームウェアのバージョン
+    pub firmware_version: String,
+    /// ESP-IDFのバージョン（ホストのシミュレータでは `"host"`）
+    pub idf_version: String,
+    /// チップの情報
+    pub chip: ChipInfo,
+    /// 起動からの経過時間（ミリ秒）
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub uptime_ms: u64,
+    /// 直前のリセット要因
+    pub reset_reason: ResetReason,
+    /// ヒープの使用状況
+    pub heap: HeapStats,
+    /// タスクごとのスタックの余裕
+    pub tasks: Vec<TaskStack>,
+}
+
+/// チップの情報
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ChipInfo {
+    /// チップのモデル（`"ESP32-S3"` など）
+    pub model: String,
+    /// チップのリビジョン
+    pub revision: u16,
+    /// CPUコア数
+    pub cores: u8,
+}
+
+/// ヒープの使用状況
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct HeapStats {
+    /// 現在の空きヒープ（バイト）
+    pub free_bytes: u32,
+    /// 起動してからの空きヒープの最小値（バイト）
+    pub min_free_bytes: u32,
+}
+
+/// タスクのスタックの余裕
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct TaskStack {
+    /// タスク名
+    pub name: String,
+    /// 起動してからのスタックの空きの最小値（バイト）
+    pub stack_high_water_mark: u32,
+}
+
+/// 直前のリセット要因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoStaticStr, VariantArray)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ResetReason {
+    /// 電源投入
+    PowerOn,
+    /// 外部ピンによるリセット
+    External,
+    /// ソフトウェアによる再起動（`reboot` コマンドなど）
+    Software,
+    /// パニック・例外
+    Panic,
+    /// 割り込みウォッチドッグ
+    InterruptWatchdog,
+    /// タスクウォッチドッグ
+    TaskWatchdog,
+    /// その他のウォッチドッグ
+    Watchdog,
+    /// ディープスリープからの復帰
+    DeepSleep,
+    /// ブラウンアウト（電圧低下）
+    Brownout,
+    /// SDIO経由のリセット
+    Sdio,
+    /// USBペリフェラル（USB Serial/JTAG）経由のリセット
+    Usb,
+    /// JTAG経由のリセット
+    Jtag,
+    /// eFuseの読み出しエラー
+    Efuse,
+    /// 電源グリッチの検出
+    PowerGlitch,
+    /// CPUのロックアップ（ダブル例外）
+    CpuLockup,
+    /// 不明
+    Unknown,
+}
+
+impl ResetReason {
+    /// すべてのリセット要因
+    pub const ALL: &'static [ResetReason] = <Self as VariantArray>::VARIANTS;
+
+    /// ワイヤー上の名前
+    pub fn as_str(&self) -> &'static str {
+        self.into()
+    }
+
+    /// 不具合を疑うべきリセット要因か（パニック・ウォッチドッグ・電源異常・eFuse・CPUロックアップ）
+    pub fn is_abnormal(&self) -> bool {
+        matches!(
+            self,
+            ResetReason::Panic
+                | ResetReason::InterruptWatchdog
+                | ResetReason::TaskWatchdog
+                | ResetReason::Watchdog
+                | ResetReason::Brownout
+                | ResetReason::Efuse
+                | ResetReason::PowerGlitch
+                | ResetReason::CpuLockup
+        )
+    }
+}
+
+impl std::fmt::Display for ResetReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_reason_wire_names() {
+        for &reason in ResetReason::ALL {
+            assert_eq!(serde_json::to_value(reason).unwrap(), serde_json::json!(reason.as_str()));
+            assert_eq!(serde_json::from_value::<ResetReason>(serde_json::json!(reason.as_str())).unwrap(), reason);
+        }
+        assert!(ResetReason::Brownout.is_abnormal());
+        assert!(ResetReason::CpuLockup.is_abnormal());
+        assert!(!ResetReason::Software.is_abnormal());
+        assert!(!ResetReason::Jtag.is_abnormal());
+    }
+}
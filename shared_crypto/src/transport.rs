@@ -0,0 +1,78 @@
+//! # トランスポート
+//!
+//! [`SecureChannel`](crate::channel::SecureChannel) の下で使う `Read + Write` の実装です。
+//!
+//! - `esp32` 機能: コンソール（USB-Serial-JTAG / UART0）と任意のUARTのトランスポート
+//! - `tauri` 機能: serialportのポートを開く設定と、切断時の再接続
+//!   （[`AsyncSecureChannel`](crate::AsyncSecureChannel) 用にtokio-serialの非同期ポートも開けます）
+
+use std::time::Duration;
+
+#[cfg(feature = "esp32")]
+mod esp32;
+#[cfg(feature = "tauri")]
+mod serial;
+
+#[cfg(feature = "esp32")]
+pub use esp32::{ConsoleTransport, UartTransport};
+#[cfg(feature = "tauri")]
+pub use serial::{available_ports, ReconnectingSerial, SerialConfig};
+#[cfg(feature = "tauri")]
+pub use tokio_serial::SerialStream;
+
+/// 再接続の待機時間（線形に増やし、上限で止める）
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// 次の待機時間
+    delay: Duration,
+    /// 最小（初期）待機時間、増分も兼ねる
+    min: Duration,
+    /// 最大待機時間
+    max: Duration,
+}
+
+impl Default for Backoff {
+    /// 1秒から始めて1秒ずつ増やし、最大5秒
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(5))
+    }
+}
+
+impl Backoff {
+    /// 最小・最大の待機時間を指定して作成
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { delay: min, min, max }
+    }
+
+    /// 次の待機時間（増やさない）
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// 次の待機時間を返し、その次の待機時間を増やす
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = std::cmp::min(self.delay + self.min, self.max);
+        delay
+    }
+
+    /// 接続に成功したときに最小待機時間に戻す
+    pub fn reset(&mut self) {
+        self.delay = self.min;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_linearly_up_to_max() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 3, 4, 5, 5, 5]);
+
+        backoff.reset();
+        assert_eq!(backoff.delay(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,85 @@
+//! ESP32（esp-idf-svc）用のトランスポート
+
+use std::io::{self, Read, Write};
+
+use esp_idf_svc::hal::delay::TickType;
+use esp_idf_svc::hal::uart::UartDriver;
+
+/// 標準入出力（コンソールUART / USB-Serial-JTAG）をまとめたトランスポート
+///
+/// ESP-IDFのログ出力と同じ回線を共有しますが、フレーミングにより受信側で分離されます。
+/// 読み取るデータがない場合は `Ok(0)` または `WouldBlock` を返すため、呼び出し側で待機してください。
+///
+/// コンソールのVFSは既定で改行を変換（送信 `\n` → `\r\n`、受信 `\r` → `\n`）し、
+/// フレーム内の0x0A/0x0Dを書き換えてしまいます。sdkconfigで
+/// `CONFIG_NEWLIB_STDOUT_LINE_ENDING_LF=y` と `CONFIG_NEWLIB_STDIN_LINE_ENDING_LF=y` を設定してください。
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsoleTransport;
+
+impl Read for ConsoleTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buf)
+    }
+}
+
+impl Write for ConsoleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// UARTドライバのトランスポート（コンソール以外のUARTを専用回線として使う場合）
+pub struct UartTransport<'d> {
+    /// UARTドライバ
+    driver: UartDriver<'d>,
+    /// 読み取りのタイムアウト（ティック）
+    read_timeout: u32,
+}
+
+impl<'d> UartTransport<'d> {
+    /// 設定済みのUARTドライバからトランスポートを作成（読み取りタイムアウト50ms）
+    pub fn new(driver: UartDriver<'d>) -> Self {
+        Self {
+            driver,
+            read_timeout: TickType::new_millis(50).ticks(),
+        }
+    }
+
+    /// 読み取りのタイムアウトを設定
+    pub fn with_read_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.read_timeout = TickType::new_millis(timeout_ms).ticks();
+        self
+    }
+
+    /// UARTドライバを取得
+    pub fn driver(&self) -> &UartDriver<'d> {
+        &self.driver
+    }
+}
+
+impl Read for UartTransport<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.driver.read(buf, self.read_timeout) {
+            // タイムアウトまでに何も受信しなかった場合（切断ではない）
+            Ok(0) if !buf.is_empty() => Err(io::ErrorKind::TimedOut.into()),
+            Ok(n) => Ok(n),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+impl Write for UartTransport<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.driver.write(buf).map_err(io::Error::other)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.driver
+            .wait_tx_done(TickType::new_millis(1000).ticks())
+            .map_err(io::Error::other)
+    }
+}
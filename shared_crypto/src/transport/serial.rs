@@ -0,0 +1,164 @@
+//! PC（serialport）用のトランスポート
+
+use std::time::Duration;
+
+use serialport::SerialPort;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use super::Backoff;
+
+/// シリアルポートの設定
+#[derive(Debug, Clone)]
+pub struct SerialConfig {
+    /// ポート名（`/dev/ttyACM0`、`COM3` など）
+    pub port_name: String,
+    /// ボーレート
+    pub baud_rate: u32,
+    /// 読み取りのタイムアウト
+    pub timeout: Duration,
+    /// DTR信号のレベル
+    pub dtr: bool,
+    /// RTS信号のレベル（ESP32の開発ボードではリセットに接続されているためfalse）
+    pub rts: bool,
+    /// ポートを開いた後、ESP32の起動を待つ時間
+    pub settle_time: Duration,
+}
+
+impl SerialConfig {
+    /// ESP32向けのデフォルト設定（115200bps 8N1、タイムアウト50ms）
+    pub fn new(port_name: impl Into<String>) -> Self {
+        Self {
+            port_name: port_name.into(),
+            baud_rate: 115_200,
+            timeout: Duration::from_millis(50),
+            dtr: true,
+            rts: false,
+            settle_time: Duration::from_millis(100),
+        }
+    }
+
+    /// ボーレートを設定
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// 読み取りのタイムアウトを設定
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 設定に従ってポートを開き、DTR/RTSを設定してESP32の起動を待つ
+    ///
+    /// DTR/RTSに対応していないアダプタもあるため、信号の設定失敗は無視します。
+    pub fn open(&self) -> Result<Box<dyn SerialPort>, serialport::Error> {
+        let mut port = serialport::new(&self.port_name, self.baud_rate)
+            .timeout(self.timeout)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .flow_control(serialport::FlowControl::None)
+            .open()?;
+
+        port.write_data_terminal_ready(self.dtr).ok();
+        port.write_request_to_send(self.rts).ok();
+        std::thread::sleep(self.settle_time);
+        Ok(port)
+    }
+
+    /// [`SerialConfig::open`] の非同期版（tokioのランタイム内で呼び出すこと）
+    ///
+    /// [`AsyncSecureChannel`](crate::AsyncSecureChannel) のトランスポートとして使えます。
+    pub async fn open_async(&self) -> Result<SerialStream, serialport::Error> {
+        let mut port = tokio_serial::new(&self.port_name, self.baud_rate)
+            .timeout(self.timeout)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .flow_control(serialport::FlowControl::None)
+            .open_native_async()?;
+
+        port.write_data_terminal_ready(self.dtr).ok();
+        port.write_request_to_send(self.rts).ok();
+        tokio::time::sleep(self.settle_time).await;
+        Ok(port)
+    }
+}
+
+/// 利用可能なシリアルポート名の一覧
+pub fn available_ports() -> Result<Vec<String>, serialport::Error> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .map(|p| p.port_name)
+        .collect())
+}
+
+/// 切断時に待機時間を延ばしながら再接続するシリアルポート
+///
+/// ```rust,no_run
+/// use esp32_tauri_crypto::transport::{ReconnectingSerial, SerialConfig};
+///
+/// let mut serial = ReconnectingSerial::new(SerialConfig::new("/dev/ttyACM0"));
+/// loop {
+///     match serial.connect() {
+///         Ok(port) => { /* 切断されるまで通信 */ }
+///         Err(e) => eprintln!("open failed: {} (retry in {:?})", e, serial.retry_delay()),
+///     }
+///     serial.wait();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReconnectingSerial {
+    /// ポートの設定
+    config: SerialConfig,
+    /// 再接続の待機時間
+    backoff: Backoff,
+}
+
+impl ReconnectingSerial {
+    /// デフォルトの待機時間（1秒から最大5秒）で作成
+    pub fn new(config: SerialConfig) -> Self {
+        Self { config, backoff: Backoff::default() }
+    }
+
+    /// 再接続の待機時間を設定
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// ポートの設定
+    pub fn config(&self) -> &SerialConfig {
+        &self.config
+    }
+
+    /// ポートを開く（成功したら待機時間を最小に戻す）
+    pub fn connect(&mut self) -> Result<Box<dyn SerialPort>, serialport::Error> {
+        let port = self.config.open()?;
+        self.backoff.reset();
+        Ok(port)
+    }
+
+    /// 次の再接続までの待機時間
+    pub fn retry_delay(&self) -> Duration {
+        self.backoff.delay()
+    }
+
+    /// 次の再接続まで待機し、その次の待機時間を延ばす
+    pub fn wait(&mut self) {
+        std::thread::sleep(self.backoff.next_delay());
+    }
+
+    /// [`ReconnectingSerial::connect`] の非同期版
+    pub async fn connect_async(&mut self) -> Result<SerialStream, serialport::Error> {
+        let port = self.config.open_async().await?;
+        self.backoff.reset();
+        Ok(port)
+    }
+
+    /// [`ReconnectingSerial::wait`] の非同期版
+    pub async fn wait_async(&mut self) {
+        tokio::time::sleep(self.backoff.next_delay()).await;
+    }
+}